@@ -0,0 +1,151 @@
+// ============================================================================
+// src/cli.rs
+// ============================================================================
+use crate::config::Config;
+use crate::database::DatabaseClient;
+use crate::topics::TopicScheme;
+use clap::Subcommand;
+use rumqttc::{AsyncClient, Event, Outgoing, Packet, QoS};
+use std::time::Duration;
+
+/// Object command flushing the device cache of a running service
+pub const FLUSH_DEVICE_CACHE: &str = "flush_device_cache";
+
+/// One-shot commands, the service runs when none is given
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print stored values of a device channel
+    History {
+        #[clap(long)]
+        obj: u32,
+        #[clap(long)]
+        device: String,
+        #[clap(long, default_value_t = 0)]
+        channel: u32,
+        /// Start time, unix time or RFC 3339 (default: 24 hours before --to)
+        #[clap(long)]
+        from: Option<String>,
+        /// End time, unix time or RFC 3339 (default: now)
+        #[clap(long)]
+        to: Option<String>,
+        /// Print the last N values. Without --from, --to, --last and --bucket
+        /// only the latest value is printed
+        #[clap(long)]
+        last: Option<usize>,
+        /// Aggregate the range into buckets of this many seconds
+        #[clap(long)]
+        bucket: Option<i64>,
+    },
+    /// Ask a running service to flush its device cache
+    FlushCache {
+        /// Object of the service (default: OBJ, then the sensors object)
+        #[clap(long)]
+        obj: Option<u32>,
+    },
+}
+
+/// Command name of an object command payload: `name` or `{"cmd":"name"}`
+pub fn parse_command(payload: &str) -> Option<String> {
+    let payload = payload.trim();
+    if payload.starts_with('{') {
+        let value: serde_json::Value = serde_json::from_str(payload).ok()?;
+        return value.get("cmd")?.as_str().map(str::to_string);
+    }
+    (!payload.is_empty()).then(|| payload.to_string())
+}
+
+/// Publish an object command with a client of its own, the status topics
+/// of the running service are left alone
+async fn send_command(config: &Config, obj: u32, command: &str) -> anyhow::Result<()> {
+    let topics = TopicScheme::from_config(config.app.mqtt_topics.as_ref())?;
+    let topic = topics.object_topic(config.ssn.account, obj, "commands");
+    let options = crate::mqtt_client::build_options(&config.app, &format!("{}cli", config.app.mqtt_broker_client_id))?;
+    let (client, mut eventloop) = AsyncClient::new(options, 10);
+    client.publish(&topic, QoS::AtLeastOnce, false, command).await?;
+
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            match eventloop.poll().await? {
+                Event::Incoming(Packet::PubAck(_)) => client.disconnect().await?,
+                Event::Outgoing(Outgoing::Disconnect) => return anyhow::Ok(()),
+                _ => {}
+            }
+        }
+    })
+    .await
+    .map_err(|_| anyhow::anyhow!("no answer from the MQTT broker"))??;
+    println!("{} sent to {}", command, topic);
+    Ok(())
+}
+
+fn parse_time(value: &str) -> anyhow::Result<i64> {
+    if let Ok(ts) = value.parse::<i64>() {
+        return Ok(ts);
+    }
+    let time = chrono::DateTime::parse_from_rfc3339(value)
+        .map_err(|e| anyhow::anyhow!("invalid time '{}': {}", value, e))?;
+    Ok(time.timestamp())
+}
+
+fn format_time(ts: i64) -> String {
+    chrono::DateTime::from_timestamp(ts, 0)
+        .map(|t| t.to_rfc3339())
+        .unwrap_or_else(|| ts.to_string())
+}
+
+pub async fn run(command: Command, config: &Config) -> anyhow::Result<()> {
+    match command {
+        Command::History {
+            obj,
+            device,
+            channel,
+            from,
+            to,
+            last,
+            bucket,
+        } => {
+            let url = config
+                .app
+                .postgrest_url
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("POSTGRESTURL is not set"))?;
+            let db = DatabaseClient::new(url, &config.app)?;
+            let account = config.ssn.account;
+
+            let range = from.is_some() || to.is_some();
+            let to = to.as_deref().map(parse_time).transpose()?.unwrap_or_else(|| chrono::Utc::now().timestamp());
+            let from = from.as_deref().map(parse_time).transpose()?.unwrap_or(to - 86400);
+
+            if let Some(count) = last {
+                for d in db.get_last_values(account, obj, &device, channel, count).await? {
+                    println!("{}\t{}\t{}", format_time(d.td_dev_ts), d.td_dev_value, d.td_action);
+                }
+            } else if let Some(bucket) = bucket {
+                println!("time\tcount\tmin\tmax\tavg");
+                for b in db
+                    .get_aggregated_values(account, obj, &device, channel, from, to, bucket)
+                    .await?
+                {
+                    println!("{}\t{}\t{}\t{}\t{:.3}", format_time(b.ts), b.count, b.min, b.max, b.avg);
+                }
+            } else if range {
+                for d in db.get_values(account, obj, &device, channel, from, to).await? {
+                    println!("{}\t{}\t{}", format_time(d.td_dev_ts), d.td_dev_value, d.td_action);
+                }
+            } else {
+                match db.get_device_value(account, obj, &device, channel).await? {
+                    Some(value) => println!("{}", value),
+                    None => println!("no value"),
+                }
+            }
+        }
+        Command::FlushCache { obj } => {
+            let obj = obj
+                .or(config.app.obj)
+                .or(config.sensors.as_ref().map(|s| s.obj))
+                .unwrap_or(0);
+            send_command(config, obj, FLUSH_DEVICE_CACHE).await?;
+        }
+    }
+    Ok(())
+}
@@ -2,7 +2,7 @@
 // src/config.rs
 // ============================================================================
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Config {
@@ -33,15 +33,200 @@ pub struct AppConfig {
     pub mqtt_broker_pass: String,
     #[serde(rename = "MQTT_BROKER_CLIENT_ID")]
     pub mqtt_broker_client_id: String,
+    /// Reconnect backoff limits, seconds
+    #[serde(rename = "MQTT_RECONNECT_MIN")]
+    pub mqtt_reconnect_min: Option<u64>,
+    #[serde(rename = "MQTT_RECONNECT_MAX")]
+    pub mqtt_reconnect_max: Option<u64>,
+    /// QoS level (0, 1, 2) per topic class
+    #[serde(rename = "MQTT_QOS")]
+    pub mqtt_qos: Option<TopicClasses>,
+    /// if 1 then messages of the topic class are retained
+    #[serde(rename = "MQTT_RETAIN")]
+    pub mqtt_retain: Option<TopicClasses>,
+    /// if 0 then the broker keeps the session (subscriptions and queued
+    /// messages) while disconnected, MQTT_BROKER_CLIENT_ID must be stable
+    #[serde(rename = "MQTT_CLEAN_SESSION")]
+    pub mqtt_clean_session: Option<u8>,
+    /// File keeping publishes made while the broker is unreachable,
+    /// kept in memory only if not set
+    #[serde(rename = "MQTT_SPOOL_FILE")]
+    pub mqtt_spool_file: Option<String>,
+    /// Spool capacity (messages), the oldest are dropped when full
+    #[serde(rename = "MQTT_SPOOL_MAX_SIZE")]
+    pub mqtt_spool_max_size: Option<usize>,
+    /// Spooled messages older than this (seconds) are dropped
+    #[serde(rename = "MQTT_SPOOL_MAX_AGE")]
+    pub mqtt_spool_max_age: Option<i64>,
+    /// Object for the status topics, defaults to the sensors object
+    #[serde(rename = "OBJ")]
+    pub obj: Option<u32>,
+    /// Topic layout, the SSN layout if not set
+    #[serde(rename = "MQTT_TOPICS")]
+    pub mqtt_topics: Option<TopicTemplates>,
+    /// Seconds between status heartbeats, 0 disables them
+    #[serde(rename = "MQTT_HEARTBEAT")]
+    pub mqtt_heartbeat: Option<u64>,
+    /// "tcp" (default), "tls", "ws" or "wss"
+    #[serde(rename = "MQTT_TRANSPORT")]
+    pub mqtt_transport: Option<String>,
+    /// CA certificates (PEM), system roots are used if not set
+    #[serde(rename = "MQTT_TLS_CA_FILE")]
+    pub mqtt_tls_ca_file: Option<String>,
+    #[serde(rename = "MQTT_TLS_CLIENT_CERT")]
+    pub mqtt_tls_client_cert: Option<String>,
+    #[serde(rename = "MQTT_TLS_CLIENT_KEY")]
+    pub mqtt_tls_client_key: Option<String>,
+    #[serde(rename = "MQTT_TLS_ALPN")]
+    pub mqtt_tls_alpn: Option<Vec<String>>,
+    /// if 1 then the broker certificate is not verified
+    #[serde(rename = "MQTT_TLS_INSECURE")]
+    pub mqtt_tls_insecure: Option<u8>,
+    /// WebSocket path, "/mqtt" by default
+    #[serde(rename = "MQTT_WS_PATH")]
+    pub mqtt_ws_path: Option<String>,
+    /// HTTP proxy (CONNECT) used to reach the broker
+    #[serde(rename = "MQTT_PROXY_HOST")]
+    pub mqtt_proxy_host: Option<String>,
+    #[serde(rename = "MQTT_PROXY_PORT")]
+    pub mqtt_proxy_port: Option<u16>,
+    #[serde(rename = "MQTT_PROXY_USER")]
+    pub mqtt_proxy_user: Option<String>,
+    #[serde(rename = "MQTT_PROXY_PASS")]
+    pub mqtt_proxy_pass: Option<String>,
+    /// If 1 then publish Home Assistant MQTT discovery messages
+    #[serde(rename = "HA_DISCOVERY")]
+    pub ha_discovery: Option<u8>,
+    #[serde(rename = "HA_DISCOVERY_PREFIX")]
+    pub ha_discovery_prefix: Option<String>,
+    /// Unit of measurement for each `dev_unit_id` of the devices table
+    #[serde(rename = "HA_UNITS")]
+    pub ha_units: Option<BTreeMap<u32, String>>,
     #[serde(rename = "POSTGRESTURL")]
     pub postgrest_url: Option<String>,
+    /// Static JWT for PostgREST
+    #[serde(rename = "POSTGRESTJWT")]
+    pub postgrest_jwt: Option<String>,
+    #[serde(rename = "POSTGRESTUSER")]
+    pub postgrest_user: Option<String>,
+    #[serde(rename = "POSTGRESTPASS")]
+    pub postgrest_pass: Option<String>,
+    /// Login RPC returning a JWT for POSTGRESTUSER/POSTGRESTPASS,
+    /// basic authentication is used if not set
+    #[serde(rename = "POSTGRESTLOGIN")]
+    pub postgrest_login: Option<String>,
+    /// Added to the Prefer header of every request
+    #[serde(rename = "POSTGRESTPREFER")]
+    pub postgrest_prefer: Option<String>,
+    /// Schema (Accept-Profile/Content-Profile headers)
+    #[serde(rename = "POSTGRESTSCHEMA")]
+    pub postgrest_schema: Option<String>,
+    /// Extra request headers
+    #[serde(rename = "POSTGRESTHEADERS")]
+    pub postgrest_headers: Option<BTreeMap<String, String>>,
+    /// Seconds a device description is cached
+    #[serde(rename = "DEVICE_CACHE_TTL")]
+    pub device_cache_ttl: Option<u64>,
+    /// Seconds an unknown device is remembered as unknown
+    #[serde(rename = "DEVICE_CACHE_NEGATIVE_TTL")]
+    pub device_cache_negative_ttl: Option<u64>,
     #[serde(rename = "LOG_TO_MQTT")]
     pub log_to_mqtt: Option<u8>,
+    /// Minimum level of the records sent to the broker (default "warn")
+    #[serde(rename = "LOG_TO_MQTT_LEVEL")]
+    pub log_to_mqtt_level: Option<String>,
+    /// Records per second sent to the broker, the excess is dropped
+    #[serde(rename = "LOG_TO_MQTT_RATE")]
+    pub log_to_mqtt_rate: Option<u32>,
+}
+
+/// Topic layout templates, see `topics.rs` for the placeholders
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct TopicTemplates {
+    pub device: Option<String>,
+    pub object: Option<String>,
+    pub account: Option<String>,
+}
+
+/// Per topic class setting: device values (`/out`), their JSON copies
+/// (`/out_json`), action events (`/event`) and commands (`/commands`, `/in`)
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct TopicClasses {
+    pub out: Option<u8>,
+    pub json: Option<u8>,
+    pub event: Option<u8>,
+    pub commands: Option<u8>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct PersistConfig {
     pub start: u8,
+    /// Storage backend: postgrest (default), postgres or sqlite
+    pub backend: Option<String>,
+    /// Topic the stored values are taken from: "out" (default, plain
+    /// values stamped with the receive time) or "out_json" (device
+    /// timestamps, actions and batches); the other one is not stored
+    pub source: Option<String>,
+    /// Connection string of the postgres backend, e.g.
+    /// "host=localhost user=ssn password=secret dbname=ssn"
+    pub postgres_url: Option<String>,
+    /// Database file of the sqlite backend
+    pub sqlite_file: Option<String>,
+    /// InfluxDB sink, used alone with `backend: influx`, next to the
+    /// backend otherwise
+    pub influx: Option<InfluxConfig>,
+    /// Change-only storage rules, the first matching one applies; values
+    /// of devices without a rule are all stored
+    pub policies: Option<Vec<PersistPolicy>>,
+    /// Rows sent to the database in one request
+    pub batch_size: Option<usize>,
+    /// Longest time a row waits for its batch
+    pub batch_interval_ms: Option<u64>,
+    /// Rows queued before incoming messages are held back
+    pub queue_size: Option<usize>,
+    /// Journal of the rows not stored yet (memory only if not set), an
+    /// extra store journals next to it, e.g. `teledata_journal.influx.jsonl`
+    pub journal_file: Option<String>,
+    /// Journal capacity (rows), the oldest are dropped when full
+    pub journal_max_size: Option<usize>,
+    /// Seconds between attempts to replay the journal
+    pub retry_interval: Option<u64>,
+    /// Columns of a unique index of ssn_teledata, replays skip the rows
+    /// already stored; every row is stored when not set
+    pub unique_key: Option<String>,
+}
+
+/// Values of the matching channels are stored when they moved beyond the
+/// deadband, at most every `min_interval` and at least every `max_interval`
+/// seconds while values keep arriving (nothing is written for a quiet
+/// channel). Selectors left out match any object, device or channel.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct PersistPolicy {
+    pub obj: Option<u32>,
+    pub device: Option<String>,
+    pub channel: Option<u32>,
+    /// Smallest change stored, in the unit of the (scaled) value
+    pub deadband: Option<f64>,
+    /// Smallest change stored, in percent of the last stored value
+    pub deadband_percent: Option<f64>,
+    pub min_interval: Option<u64>,
+    pub max_interval: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct InfluxConfig {
+    /// Server URL, e.g. "http://localhost:8086"
+    pub url: String,
+    /// InfluxDB 2 organization and bucket, written with `token`
+    pub org: Option<String>,
+    pub bucket: Option<String>,
+    pub token: Option<String>,
+    /// InfluxDB 1 database, written with `user`/`pass` if set
+    pub database: Option<String>,
+    pub user: Option<String>,
+    pub pass: Option<String>,
+    /// Measurement of the devices without `dev_grp` (default "ssn")
+    pub measurement: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -52,14 +237,20 @@ pub struct BotConfig {
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct SensorsConfig {
     pub obj: u32,
-    pub gpio: Option<GpioConfig>,
-    pub ds18b20: Option<Ds18b20Config>,
-    pub watchdog_tcp: Option<WatchdogTcpConfig>,
+    /// Driver sections (gpio, ds18b20, watchdog_tcp, ...) keyed by name,
+    /// each one is parsed by its driver in crate::sensors
+    #[serde(flatten)]
+    pub sections: BTreeMap<String, serde_yaml::Value>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct GpioConfig {
     pub scan_rate: u32,
+    pub hart_beat_timeout: Option<u32>,
+    /// Sampling period (ms) used for debouncing and pulse counting
+    pub poll_ms: Option<u64>,
+    /// File where pulse counter totals are kept between restarts
+    pub counter_state: Option<String>,
     pub pins: Vec<GpioPin>,
 }
 
@@ -68,10 +259,15 @@ pub struct GpioPin {
     pub id: String,
     pub gpiochip: u32,
     pub number: u32,
+    /// "in", "out" or "counter"
     #[serde(rename = "type")]
     pub pin_type: String,
     pub name: String,
     pub comment: Option<String>,
+    /// Input must be stable for this time (ms) before a change is accepted
+    pub debounce_ms: Option<u64>,
+    /// Counted edge for "counter" pins: "rising" (default), "falling" or "both"
+    pub edge: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -107,6 +303,81 @@ pub struct WatchdogDestination {
     pub command: String,
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SysfsConfig {
+    pub scan_rate: u32,
+    /// sysfs mount point, "/sys" by default
+    pub root: Option<String>,
+    pub channels: Vec<SysfsChannel>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SysfsChannel {
+    pub device: String,
+    pub channel: u32,
+    pub name: Option<String>,
+    /// hwmon chip name (content of class/hwmon/hwmonN/name)
+    pub hwmon: Option<String>,
+    /// IIO device name (content of bus/iio/devices/iio:deviceN/name)
+    pub iio: Option<String>,
+    /// Attribute file of the chip, or a path relative to root when
+    /// neither hwmon nor iio is given
+    pub attribute: String,
+    /// Published value is (raw + offset) * scale
+    pub scale: Option<f64>,
+    pub offset: Option<f64>,
+    pub unit: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ExecConfig {
+    pub commands: Vec<ExecCommand>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ExecCommand {
+    pub id: String,
+    pub channel: Option<u32>,
+    pub name: Option<String>,
+    /// Shell command line, run with `sh -c`
+    pub command: String,
+    /// Seconds between runs
+    pub interval: u32,
+    /// Seconds before the command is killed
+    pub timeout: Option<u32>,
+    /// Number is taken from the first capture group (or the whole match)
+    pub regex: Option<String>,
+    /// Dotted path into JSON output, e.g. "data.sensors.0.value"
+    pub json_path: Option<String>,
+    pub unit: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct I2cConfig {
+    pub buses: Vec<I2cBusConfig>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct I2cBusConfig {
+    /// Bus number N of /dev/i2c-N
+    pub bus: u32,
+    pub scan_rate: u32,
+    pub devices: Vec<I2cDevice>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct I2cDevice {
+    pub id: String,
+    /// "bme280", "sht3x" or "ads1115"
+    pub chip: String,
+    pub address: u16,
+    pub name: Option<String>,
+    /// ADS1115 full scale range in volts (6.144, 4.096, 2.048, ...)
+    pub gain: Option<f64>,
+    /// ADS1115 single-ended inputs to read (0..3)
+    pub inputs: Option<Vec<u8>>,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ActionConfig {
     pub id: u32,
@@ -1,8 +1,14 @@
 // ============================================================================
 // src/database.rs
 // ============================================================================
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use crate::config::AppConfig;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::fmt;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct DeviceInfo {
@@ -15,9 +21,14 @@ pub struct DeviceInfo {
     pub dev_scale: Option<String>,
     pub dev_unit_id: Option<u32>,
     pub dev_grp: Option<String>,
+    /// Persistence policy, overrides `persist.policies` (optional columns)
+    pub dev_deadband: Option<f64>,
+    pub dev_deadband_percent: Option<f64>,
+    pub dev_min_interval: Option<u64>,
+    pub dev_max_interval: Option<u64>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TeleData {
     pub td_account: u32,
     pub td_object: u32,
@@ -29,98 +40,705 @@ pub struct TeleData {
     pub td_action: u32,
 }
 
+impl TeleData {
+    pub fn new(
+        account: u32,
+        object: u32,
+        device: &str,
+        channel: u32,
+        value: f64,
+        action_id: u32,
+        dev_ts: Option<i64>,
+    ) -> Self {
+        let ts = chrono::Utc::now().timestamp();
+        Self {
+            td_account: account,
+            td_object: object,
+            td_device: device.to_string(),
+            td_channel: channel,
+            td_dev_ts: dev_ts.unwrap_or(ts),
+            td_store_ts: ts,
+            td_dev_value: value,
+            td_action: action_id,
+        }
+    }
+}
+
+/// Error body returned by PostgREST
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PostgrestError {
+    pub code: Option<String>,
+    pub message: Option<String>,
+    pub details: Option<String>,
+    pub hint: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum DbError {
+    /// The request did not get a response (connect error, timeout...)
+    Connection(reqwest::Error),
+    /// Non 2xx response
+    Http {
+        status: reqwest::StatusCode,
+        error: PostgrestError,
+    },
+    /// Response body not in the expected format
+    Decode(String),
+    /// Direct PostgreSQL connection error
+    Postgres(tokio_postgres::Error),
+    /// Embedded SQLite error
+    Sqlite(rusqlite::Error),
+    /// Something the rows need is not available yet (a device description)
+    Unavailable(String),
+}
+
+impl DbError {
+    /// Errors which may go away if the request is sent again later.
+    /// Credential and permission errors (an expired JWT, a missing grant)
+    /// are too: they are fixed in the configuration, not in the rows.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DbError::Connection(_) => true,
+            DbError::Http { status, .. } => {
+                status.is_server_error()
+                    || *status == reqwest::StatusCode::REQUEST_TIMEOUT
+                    || *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    || *status == reqwest::StatusCode::UNAUTHORIZED
+                    || *status == reqwest::StatusCode::FORBIDDEN
+            }
+            DbError::Decode(_) => false,
+            // Lost connection, or connection (08), invalid authorization (28),
+            // transaction rollback (40), resources (53) and operator
+            // intervention (57) classes, insufficient privilege
+            DbError::Postgres(e) => {
+                e.is_closed()
+                    || e.code().map_or(e.as_db_error().is_none(), |code| {
+                        ["08", "28", "40", "53", "57"].iter().any(|class| code.code().starts_with(class))
+                            || code.code() == "42501"
+                    })
+            }
+            DbError::Sqlite(e) => matches!(
+                e.sqlite_error_code(),
+                Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+            ),
+            DbError::Unavailable(_) => true,
+        }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Connection(e) => write!(f, "database connection error: {}", e),
+            DbError::Http { status, error } => {
+                write!(f, "database error {}", status)?;
+                if let Some(code) = &error.code {
+                    write!(f, " [{}]", code)?;
+                }
+                if let Some(message) = &error.message {
+                    write!(f, ": {}", message)?;
+                }
+                if let Some(details) = &error.details {
+                    write!(f, " ({})", details)?;
+                }
+                if let Some(hint) = &error.hint {
+                    write!(f, ", hint: {}", hint)?;
+                }
+                Ok(())
+            }
+            DbError::Decode(e) => write!(f, "database response decode error: {}", e),
+            DbError::Postgres(e) => write!(f, "PostgreSQL error: {}", e),
+            DbError::Sqlite(e) => write!(f, "SQLite error: {}", e),
+            DbError::Unavailable(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+impl From<tokio_postgres::Error> for DbError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        DbError::Postgres(e)
+    }
+}
+
+impl From<rusqlite::Error> for DbError {
+    fn from(e: rusqlite::Error) -> Self {
+        DbError::Sqlite(e)
+    }
+}
+
+impl From<reqwest::Error> for DbError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            DbError::Decode(e.to_string())
+        } else {
+            DbError::Connection(e)
+        }
+    }
+}
+
+/// Turn a non 2xx response into a `DbError::Http` with the PostgREST error
+/// body (InfluxDB errors have the same `code` and `message` fields)
+pub(crate) async fn check(response: reqwest::Response) -> Result<reqwest::Response, DbError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    let error = serde_json::from_str(&body).unwrap_or_else(|_| PostgrestError {
+        message: Some(body.trim().to_string()).filter(|m| !m.is_empty()),
+        ..Default::default()
+    });
+    Err(DbError::Http { status, error })
+}
+
+/// How requests are authenticated, see the POSTGREST* settings
+#[derive(Debug, Clone, Default)]
+pub struct DbAuth {
+    /// Static JWT sent as bearer token
+    pub jwt: Option<String>,
+    pub user: Option<String>,
+    pub pass: Option<String>,
+    /// RPC exchanging user/password for a JWT, e.g. "login".
+    /// Without it user/password are sent as HTTP basic authentication.
+    pub login_rpc: Option<String>,
+    /// Value added to the Prefer header of every request
+    pub prefer: Option<String>,
+    /// Schema selected with Accept-Profile/Content-Profile
+    pub schema: Option<String>,
+    /// Other headers, e.g. a role header checked by a proxy
+    pub headers: BTreeMap<String, String>,
+}
+
+impl DbAuth {
+    pub fn from_config(app: &AppConfig) -> Self {
+        Self {
+            jwt: app.postgrest_jwt.clone(),
+            user: app.postgrest_user.clone(),
+            pass: app.postgrest_pass.clone(),
+            login_rpc: app.postgrest_login.clone(),
+            prefer: app.postgrest_prefer.clone(),
+            schema: app.postgrest_schema.clone(),
+            headers: app.postgrest_headers.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// JWT returned by the login RPC
+struct Token {
+    value: String,
+    /// Expiry (unix time) from the `exp` claim
+    expires: Option<i64>,
+}
+
+/// Seconds before expiry when the token is renewed
+const TOKEN_REFRESH_MARGIN: i64 = 60;
+
+/// Expiry of a JWT, None if it has no `exp` claim
+fn jwt_expiry(token: &str) -> Option<i64> {
+    use base64::Engine;
+    let payload = token.split('.').nth(1)?;
+    let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&payload).ok()?;
+    claims.get("exp")?.as_i64()
+}
+
+/// Aggregated values of one time bucket
+#[derive(Debug, Clone, Serialize)]
+pub struct ValueBucket {
+    /// Bucket start (unix time)
+    pub ts: i64,
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+}
+
+/// Group values sorted by device time into buckets aligned on `from`
+fn aggregate(data: &[TeleData], from: i64, bucket: i64) -> Vec<ValueBucket> {
+    let mut buckets: Vec<ValueBucket> = Vec::new();
+    for d in data {
+        let ts = from + (d.td_dev_ts - from).div_euclid(bucket) * bucket;
+        let v = d.td_dev_value;
+        match buckets.last_mut() {
+            Some(b) if b.ts == ts => {
+                b.min = b.min.min(v);
+                b.max = b.max.max(v);
+                // avg holds the sum until all values are added
+                b.avg += v;
+                b.count += 1;
+            }
+            _ => buckets.push(ValueBucket {
+                ts,
+                count: 1,
+                min: v,
+                max: v,
+                avg: v,
+            }),
+        }
+    }
+    for b in &mut buckets {
+        b.avg /= b.count as f64;
+    }
+    buckets
+}
+
+const DEVICE_CACHE_TTL: u64 = 3600;
+const DEVICE_CACHE_NEGATIVE_TTL: u64 = 300;
+/// Lookups are not sent again for this long after one failed
+const DEVICE_CACHE_ERROR_TTL: Duration = Duration::from_secs(30);
+/// Characters escaped in a query value, the unreserved ones are kept
+const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.').remove(b'~');
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Rows asked for per history request
+const VALUES_PAGE: usize = 10000;
+
+/// Text as a URL query value, device names may contain `&`, `#`, spaces...
+pub(crate) fn query_value(value: &str) -> String {
+    utf8_percent_encode(value, QUERY_VALUE).to_string()
+}
+
+/// Row of the channel, or the first row of the device if there is none
+fn channel_row(rows: &[DeviceInfo], channel: u32) -> Option<&DeviceInfo> {
+    rows.iter()
+        .find(|r| r.channel.trim().parse() == Ok(channel))
+        .or(rows.first())
+}
+
+/// HTTP client for the database services: a request never hangs longer
+/// than REQUEST_TIMEOUT, the writer retries it then
+pub(crate) fn http_client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+}
+
+/// Rows of the devices table for one (account, object, device), empty
+/// when the device is unknown. After a failed lookup the previous rows
+/// (if any) are kept with `failed` set, `known` tells if there ever was a
+/// successful one.
+struct CachedDevice {
+    rows: Vec<DeviceInfo>,
+    fetched: Instant,
+    failed: bool,
+    known: bool,
+}
+
+/// Description of a device channel from the cache
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum CachedInfo {
+    /// The device was looked up, `None` when it has no row
+    Known(Option<DeviceInfo>),
+    /// Not looked up successfully yet, it is being fetched
+    Pending,
+}
+
+type DeviceKey = (u32, u32, String);
+
 pub struct DatabaseClient {
     base_url: String,
     client: reqwest::Client,
-    device_cache: tokio::sync::Mutex<HashMap<String, DeviceInfo>>,
+    auth: DbAuth,
+    token: tokio::sync::Mutex<Option<Token>>,
+    device_cache: Mutex<HashMap<DeviceKey, CachedDevice>>,
+    /// Devices being fetched in the background
+    refreshing: Mutex<HashSet<DeviceKey>>,
+    cache_ttl: Duration,
+    cache_negative_ttl: Duration,
 }
 
 impl DatabaseClient {
-    pub fn new(base_url: String) -> Self {
-        Self {
+    pub fn new(base_url: String, app: &AppConfig) -> anyhow::Result<Self> {
+        Ok(Self {
             base_url,
-            client: reqwest::Client::new(),
-            device_cache: tokio::sync::Mutex::new(HashMap::new()),
+            client: http_client()?,
+            auth: DbAuth::from_config(app),
+            token: tokio::sync::Mutex::new(None),
+            device_cache: Mutex::new(HashMap::new()),
+            refreshing: Mutex::new(HashSet::new()),
+            cache_ttl: Duration::from_secs(app.device_cache_ttl.unwrap_or(DEVICE_CACHE_TTL)),
+            cache_negative_ttl: Duration::from_secs(
+                app.device_cache_negative_ttl.unwrap_or(DEVICE_CACHE_NEGATIVE_TTL),
+            ),
+        })
+    }
+
+    /// Bearer token from the login RPC, renewed shortly before it expires
+    async fn login_token(&self, rpc: &str) -> Result<String, DbError> {
+        let mut token = self.token.lock().await;
+        let now = chrono::Utc::now().timestamp();
+        if let Some(t) = token.as_ref() {
+            if t.expires.map_or(true, |exp| exp - TOKEN_REFRESH_MARGIN > now) {
+                return Ok(t.value.clone());
+            }
+        }
+
+        let url = format!("{}/rpc/{}", self.base_url, rpc);
+        let body = serde_json::json!({
+            "username": self.auth.user,
+            "password": self.auth.pass,
+        });
+        let response = check(self.client.post(&url).json(&body).send().await?).await?;
+        let body: serde_json::Value = response.json().await?;
+
+        // A plain string, {"token": ...} or [{"token": ...}]
+        let value = match &body {
+            serde_json::Value::Array(rows) => rows.first().and_then(|r| r.get("token")),
+            serde_json::Value::Object(_) => body.get("token"),
+            _ => Some(&body),
         }
+        .and_then(|t| t.as_str())
+        .ok_or_else(|| DbError::Decode(format!("no token in login response: {}", body)))?
+        .to_string();
+
+        let expires = jwt_expiry(&value);
+        log::info!("PostgREST login as {}: token expires {:?}", self.auth.user.as_deref().unwrap_or(""), expires);
+        *token = Some(Token {
+            value: value.clone(),
+            expires,
+        });
+        Ok(value)
     }
 
-    pub async fn get_device_info(&self, account: u32, device: &str) -> anyhow::Result<Option<DeviceInfo>> {
-        // Check cache first
-        {
-            let cache = self.device_cache.lock().await;
-            if let Some(info) = cache.get(device) {
-                return Ok(Some(info.clone()));
+    /// Send a request with the authentication and common headers, a rejected
+    /// login token is renewed and the request sent once more
+    async fn send(
+        &self,
+        build: impl Fn(&reqwest::Client) -> reqwest::RequestBuilder,
+        prefer: Option<&str>,
+    ) -> Result<reqwest::Response, DbError> {
+        for attempt in 0..2 {
+            let mut request = build(&self.client);
+
+            if let Some(rpc) = &self.auth.login_rpc {
+                request = request.bearer_auth(self.login_token(rpc).await?);
+            } else if let Some(jwt) = &self.auth.jwt {
+                request = request.bearer_auth(jwt);
+            } else if let Some(user) = &self.auth.user {
+                request = request.basic_auth(user, self.auth.pass.as_ref());
+            }
+
+            let prefer = [prefer, self.auth.prefer.as_deref()]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(",");
+            if !prefer.is_empty() {
+                request = request.header("Prefer", prefer);
+            }
+            if let Some(schema) = &self.auth.schema {
+                request = request
+                    .header("Accept-Profile", schema)
+                    .header("Content-Profile", schema);
+            }
+            for (name, value) in &self.auth.headers {
+                request = request.header(name, value);
+            }
+
+            let response = request.send().await?;
+            if response.status() == reqwest::StatusCode::UNAUTHORIZED && self.auth.login_rpc.is_some() && attempt == 0 {
+                log::warn!("PostgREST token rejected, logging in again");
+                *self.token.lock().await = None;
+                continue;
             }
+            return check(response).await;
         }
+        unreachable!("the second attempt always returns")
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, DbError> {
+        let response = self.send(|client| client.get(url), None).await?;
+        let body = response.bytes().await?;
+        serde_json::from_slice(&body).map_err(|e| DbError::Decode(e.to_string()))
+    }
 
-        // Fetch from database
+    /// Description of a device channel from the cache only, never waits
+    /// for the database: a missing or expired entry is fetched in the
+    /// background, an expired one is used meanwhile
+    pub fn cached_device_info(self: &Arc<Self>, account: u32, object: u32, device: &str, channel: u32) -> CachedInfo {
+        let key = (account, object, device.to_string());
+        let (rows, known, fresh) = match self.lock_cache().get(&key) {
+            Some(cached) => (cached.rows.clone(), cached.known, cached.fetched.elapsed() < self.ttl(cached)),
+            None => (Vec::new(), false, false),
+        };
+
+        if !fresh && self.refreshing.lock().unwrap_or_else(|e| e.into_inner()).insert(key.clone()) {
+            let db = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = db.fetch_device_rows(&key).await {
+                    log::warn!("Device info for {} error: {}", key.2, e);
+                }
+                db.refreshing.lock().unwrap_or_else(|e| e.into_inner()).remove(&key);
+            });
+        }
+        if known {
+            CachedInfo::Known(channel_row(&rows, channel).cloned())
+        } else {
+            CachedInfo::Pending
+        }
+    }
+
+    fn lock_cache(&self) -> std::sync::MutexGuard<'_, HashMap<DeviceKey, CachedDevice>> {
+        self.device_cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn ttl(&self, cached: &CachedDevice) -> Duration {
+        if cached.failed {
+            DEVICE_CACHE_ERROR_TTL
+        } else if cached.rows.is_empty() {
+            self.cache_negative_ttl
+        } else {
+            self.cache_ttl
+        }
+    }
+
+    /// Query the rows of a device into the cache. A failure is cached too,
+    /// with the previous rows, so that the database is not asked again for
+    /// every value while it is unreachable.
+    async fn fetch_device_rows(&self, key: &DeviceKey) -> Result<Vec<DeviceInfo>, DbError> {
+        let (account, object, device) = key;
         let url = format!(
-            "{}/devices?account=eq.{}&device=eq.{}&limit=1",
-            self.base_url, account, device
+            "{}/devices?account=eq.{}&object=eq.{}&device=eq.{}",
+            self.base_url,
+            account,
+            object,
+            query_value(device)
         );
+        let result = self.get_json::<Vec<DeviceInfo>>(&url).await;
+
+        let mut cache = self.lock_cache();
+        let entry = match result {
+            Ok(ref rows) => CachedDevice {
+                rows: rows.clone(),
+                fetched: Instant::now(),
+                failed: false,
+                known: true,
+            },
+            Err(_) => {
+                let (rows, known) = cache.remove(key).map_or((Vec::new(), false), |c| (c.rows, c.known));
+                CachedDevice {
+                    rows,
+                    fetched: Instant::now(),
+                    failed: true,
+                    known,
+                }
+            }
+        };
+        cache.insert(key.clone(), entry);
+        result
+    }
 
-        let response = self.client.get(&url).send().await?;
-        let devices: Vec<DeviceInfo> = response.json().await?;
+    /// Load all devices of an account into the cache, returns the rows
+    pub async fn preload_devices(&self, account: u32) -> Result<Vec<DeviceInfo>, DbError> {
+        let rows = self.get_devices(account).await?;
 
-        if let Some(device_info) = devices.into_iter().next() {
-            let mut cache = self.device_cache.lock().await;
-            cache.insert(device.to_string(), device_info.clone());
-            Ok(Some(device_info))
-        } else {
-            Ok(None)
+        let mut grouped: HashMap<DeviceKey, Vec<DeviceInfo>> = HashMap::new();
+        for row in &rows {
+            grouped
+                .entry((row.account, row.object, row.device.clone()))
+                .or_default()
+                .push(row.clone());
         }
+
+        let mut cache = self.lock_cache();
+        let fetched = Instant::now();
+        for (key, rows) in grouped {
+            cache.insert(
+                key,
+                CachedDevice {
+                    rows,
+                    fetched,
+                    failed: false,
+                    known: true,
+                },
+            );
+        }
+        log::info!("Device cache: {} rows of account {} loaded", rows.len(), account);
+        Ok(rows)
+    }
+
+    /// Drop all cached devices, they are fetched again on next use
+    pub async fn flush_device_cache(&self) {
+        let mut cache = self.lock_cache();
+        log::info!("Device cache: {} devices flushed", cache.len());
+        cache.clear();
+    }
+
+    /// All devices of an account
+    pub async fn get_devices(&self, account: u32) -> Result<Vec<DeviceInfo>, DbError> {
+        let url = format!("{}/devices?account=eq.{}", self.base_url, account);
+        self.get_json(&url).await
     }
 
+    /// Latest value of a device channel
     pub async fn get_device_value(
         &self,
         account: u32,
         object: u32,
         device: &str,
         channel: u32,
-    ) -> anyhow::Result<Option<f64>> {
+    ) -> Result<Option<f64>, DbError> {
+        let data = self.get_last_values(account, object, device, channel, 1).await?;
+        Ok(data.into_iter().next().map(|d| d.td_dev_value))
+    }
+
+    /// Last `count` values of a device channel, newest first
+    pub async fn get_last_values(
+        &self,
+        account: u32,
+        object: u32,
+        device: &str,
+        channel: u32,
+        count: usize,
+    ) -> Result<Vec<TeleData>, DbError> {
         let url = format!(
-            "{}/ssn_teledata?td_account=eq.{}&td_object=eq.{}&td_device=eq.{}&order=td_store_ts.desc&limit=1",
-            self.base_url, account, object, device
+            "{}/ssn_teledata?td_account=eq.{}&td_object=eq.{}&td_device=eq.{}&td_channel=eq.{}&order=td_dev_ts.desc,td_store_ts.desc&limit={}",
+            self.base_url,
+            account,
+            object,
+            query_value(device),
+            channel,
+            count
         );
-
-        let response = self.client.get(&url).send().await?;
-        let data: Vec<TeleData> = response.json().await?;
-
-        Ok(data.into_iter().next().map(|d| d.td_dev_value))
+        self.get_json(&url).await
     }
 
-    pub async fn set_device_value(
+    /// Values of a device channel with device time in [from, to), oldest
+    /// first. Fetched in pages until an empty one, so that a `db-max-rows`
+    /// limit of the server never truncates the result. The order covers
+    /// all columns: rows equal in all of them are interchangeable, the
+    /// pages neither skip nor repeat a value.
+    pub async fn get_values(
         &self,
         account: u32,
         object: u32,
         device: &str,
         channel: u32,
-        value: f64,
-        action_id: u32,
-        dev_ts: Option<i64>,
-    ) -> anyhow::Result<()> {
-        let ts = chrono::Utc::now().timestamp();
-        let dev_ts = dev_ts.unwrap_or(ts);
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<TeleData>, DbError> {
+        let url = format!(
+            "{}/ssn_teledata?td_account=eq.{}&td_object=eq.{}&td_device=eq.{}&td_channel=eq.{}&td_dev_ts=gte.{}&td_dev_ts=lt.{}&order=td_dev_ts.asc,td_store_ts.asc,td_dev_value.asc,td_action.asc",
+            self.base_url,
+            account,
+            object,
+            query_value(device),
+            channel,
+            from,
+            to
+        );
+        let mut values = Vec::new();
+        loop {
+            let page: Vec<TeleData> = self
+                .get_json(&format!("{}&limit={}&offset={}", url, VALUES_PAGE, values.len()))
+                .await?;
+            if page.is_empty() {
+                return Ok(values);
+            }
+            values.extend(page);
+        }
+    }
 
-        let data = vec![TeleData {
-            td_account: account,
-            td_object: object,
-            td_device: device.to_string(),
-            td_channel: channel,
-            td_dev_ts: dev_ts,
-            td_store_ts: ts,
-            td_dev_value: value,
-            td_action: action_id,
-        }];
+    /// Min/max/avg of a device channel per `bucket` seconds in [from, to)
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_aggregated_values(
+        &self,
+        account: u32,
+        object: u32,
+        device: &str,
+        channel: u32,
+        from: i64,
+        to: i64,
+        bucket: i64,
+    ) -> Result<Vec<ValueBucket>, DbError> {
+        let data = self.get_values(account, object, device, channel, from, to).await?;
+        Ok(aggregate(&data, from, bucket.max(1)))
+    }
 
-        let url = format!("{}/ssn_teledata", self.base_url);
-        log::info!("set device value. url={} data={:?}", url, data);
-        self.client
-            .post(&url)
-            .json(&data)
-            .send()
-            .await?;
+    /// Insert rows into ssn_teledata with one POST. With `unique_key` (columns
+    /// of a unique index) rows already stored are skipped, so a batch can be
+    /// sent again safely.
+    pub async fn insert_teledata(&self, rows: &[TeleData], unique_key: Option<&str>) -> Result<(), DbError> {
+        let mut url = format!("{}/ssn_teledata", self.base_url);
+        let mut prefer = None;
+        if let Some(key) = unique_key {
+            url = format!("{}?on_conflict={}", url, key);
+            prefer = Some("resolution=ignore-duplicates,return=minimal");
+        }
+        log::debug!("insert teledata. url={} rows={}", url, rows.len());
+        self.send(|client| client.post(&url).json(rows), prefer).await?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn http(status: u16) -> DbError {
+        DbError::Http {
+            status: reqwest::StatusCode::from_u16(status).unwrap(),
+            error: PostgrestError::default(),
+        }
+    }
+
+    #[test]
+    fn retryable_http_errors() {
+        for status in [401, 403, 408, 429, 500, 503] {
+            assert!(http(status).is_retryable(), "{}", status);
+        }
+        for status in [400, 404, 409, 422] {
+            assert!(!http(status).is_retryable(), "{}", status);
+        }
+        assert!(DbError::Unavailable("device".into()).is_retryable());
+        assert!(!DbError::Decode("body".into()).is_retryable());
+    }
+
+    fn values(values: &[(i64, f64)]) -> Vec<TeleData> {
+        values
+            .iter()
+            .map(|(ts, v)| TeleData::new(2, 64, "t1", 0, *v, 0, Some(*ts)))
+            .collect()
+    }
+
+    fn buckets(buckets: &[ValueBucket]) -> Vec<(i64, usize, f64, f64, f64)> {
+        buckets.iter().map(|b| (b.ts, b.count, b.min, b.max, b.avg)).collect()
+    }
+
+    #[test]
+    fn buckets_are_aligned_on_from() {
+        let data = values(&[(1005, 1.0), (1064, 3.0), (1065, 5.0), (1190, 7.0), (1244, 9.0)]);
+        assert_eq!(
+            buckets(&aggregate(&data, 1005, 60)),
+            // No bucket without values
+            [(1005, 2, 1.0, 3.0, 2.0), (1065, 1, 5.0, 5.0, 5.0), (1185, 2, 7.0, 9.0, 8.0)]
+        );
+    }
+
+    #[test]
+    fn values_before_from() {
+        let data = values(&[(-130, 1.0), (-61, 2.0), (-60, 3.0), (-1, 4.0), (0, 5.0)]);
+        assert_eq!(
+            buckets(&aggregate(&data, 0, 60)),
+            [(-180, 1, 1.0, 1.0, 1.0), (-120, 1, 2.0, 2.0, 2.0), (-60, 2, 3.0, 4.0, 3.5), (0, 1, 5.0, 5.0, 5.0)]
+        );
+        let data = values(&[(95, 1.0), (99, 2.0), (100, 3.0)]);
+        assert_eq!(
+            buckets(&aggregate(&data, 100, 10)),
+            [(90, 2, 1.0, 2.0, 1.5), (100, 1, 3.0, 3.0, 3.0)]
+        );
+    }
+
+    #[test]
+    fn no_values_no_buckets() {
+        assert!(aggregate(&[], 0, 60).is_empty());
+    }
+}
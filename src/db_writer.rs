@@ -0,0 +1,437 @@
+// ============================================================================
+// src/db_writer.rs
+// ============================================================================
+use crate::config::PersistConfig;
+use crate::database::{DbError, TeleData};
+use crate::spool::{Spool, SpoolItem};
+use crate::store::TeleStore;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+const BATCH_SIZE: usize = 100;
+const BATCH_INTERVAL: Duration = Duration::from_millis(1000);
+const QUEUE_SIZE: usize = 1000;
+const JOURNAL_MAX_SIZE: usize = 100000;
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+impl SpoolItem for TeleData {
+    fn timestamp(&self) -> i64 {
+        self.td_store_ts
+    }
+}
+
+/// Write statistics since the last report
+#[derive(Debug, Default)]
+struct WriterStats {
+    rows: u64,
+    batches: u64,
+    journaled_rows: u64,
+    rejected_rows: u64,
+    total_latency: Duration,
+    max_latency: Duration,
+}
+
+/// Background writer for ssn_teledata: rows are queued and sent in
+/// batches when `batch_size` rows are pending or `batch_interval_ms`
+/// passed since the first one. A full queue blocks the sender.
+/// Rows which could not be stored for a retryable reason go to the
+/// journal and are replayed in order, new rows wait behind them.
+/// Every [`TeleStore`] has its own queue and journal: a store which is
+/// down does not hold back the others, nor makes them store rows twice.
+pub struct DbWriter {
+    queues: Vec<(mpsc::Sender<TeleData>, tokio::task::JoinHandle<()>)>,
+}
+
+struct Writer {
+    name: &'static str,
+    store: Arc<dyn TeleStore>,
+    journal: Spool<TeleData>,
+    batch_size: usize,
+    unique_key: Option<String>,
+    stats: WriterStats,
+}
+
+impl DbWriter {
+    pub fn start(stores: Vec<Arc<dyn TeleStore>>, config: Option<&PersistConfig>) -> anyhow::Result<Self> {
+        let batch_size = config.and_then(|c| c.batch_size).unwrap_or(BATCH_SIZE).max(1);
+        let interval = config
+            .and_then(|c| c.batch_interval_ms)
+            .map(Duration::from_millis)
+            .unwrap_or(BATCH_INTERVAL);
+        let queue_size = config.and_then(|c| c.queue_size).unwrap_or(QUEUE_SIZE).max(1);
+        let retry = config
+            .and_then(|c| c.retry_interval)
+            .map(Duration::from_secs)
+            .unwrap_or(RETRY_INTERVAL);
+        let journal_file = config.and_then(|c| c.journal_file.as_deref());
+
+        let mut queues = Vec::with_capacity(stores.len());
+        for (i, store) in stores.into_iter().enumerate() {
+            let name = store.name();
+            // The first store keeps the configured journal file
+            let path = journal_file.map(|path| match i {
+                0 => path.to_string(),
+                _ => store_journal(path, name),
+            });
+            let journal = Spool::open(
+                path.as_deref(),
+                config.and_then(|c| c.journal_max_size).unwrap_or(JOURNAL_MAX_SIZE),
+                None,
+            )?;
+
+            let writer = Writer {
+                name,
+                store,
+                journal,
+                batch_size,
+                unique_key: crate::store::unique_key(config),
+                stats: WriterStats::default(),
+            };
+            let (tx, rx) = mpsc::channel(queue_size);
+            queues.push((tx, tokio::spawn(run(writer, rx, interval, retry))));
+            log::info!(
+                "Database writer {}: batches of {} rows / {} ms, queue {}",
+                name,
+                batch_size,
+                interval.as_millis(),
+                queue_size
+            );
+        }
+        Ok(Self { queues })
+    }
+
+    /// Store the pending rows and stop
+    pub async fn close(self) {
+        for (tx, task) in self.queues {
+            drop(tx);
+            let _ = task.await;
+        }
+    }
+
+    /// Queue a row for every store, waits while a queue is full
+    pub async fn write(&self, row: TeleData) -> anyhow::Result<()> {
+        for (tx, _) in &self.queues {
+            if tx.capacity() == 0 {
+                log::warn!("Database writer queue is full, waiting");
+            }
+            tx.send(row.clone())
+                .await
+                .map_err(|_| anyhow::anyhow!("database writer stopped"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Journal of an additional store, named after the configured one:
+/// `teledata_journal.jsonl` becomes `teledata_journal.influx.jsonl`
+fn store_journal(path: &str, store: &str) -> String {
+    match path.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && !stem.ends_with('/') && !ext.contains('/') => {
+            format!("{}.{}.{}", stem, store, ext)
+        }
+        _ => format!("{}.{}", path, store),
+    }
+}
+
+async fn run(mut writer: Writer, mut rx: mpsc::Receiver<TeleData>, interval: Duration, retry: Duration) {
+    let mut batch: Vec<TeleData> = Vec::with_capacity(writer.batch_size);
+    let mut deadline = tokio::time::Instant::now();
+    let mut retry_at = tokio::time::Instant::now();
+    let mut report = tokio::time::interval(REPORT_INTERVAL);
+    report.tick().await;
+
+    loop {
+        tokio::select! {
+            row = rx.recv() => {
+                let Some(row) = row else {
+                    writer.flush(&mut batch).await;
+                    return;
+                };
+                if batch.is_empty() {
+                    deadline = tokio::time::Instant::now() + interval;
+                }
+                batch.push(row);
+                if batch.len() >= writer.batch_size {
+                    writer.flush(&mut batch).await;
+                }
+            }
+            _ = tokio::time::sleep_until(deadline), if !batch.is_empty() => {
+                writer.flush(&mut batch).await;
+            }
+            _ = tokio::time::sleep_until(retry_at), if !writer.journal.is_empty() => {
+                writer.replay().await;
+                retry_at = tokio::time::Instant::now() + retry;
+            }
+            _ = report.tick() => {
+                writer.report(rx.len());
+            }
+        }
+    }
+}
+
+impl Writer {
+    async fn insert(&mut self, rows: &[TeleData]) -> Result<(), DbError> {
+        let started = Instant::now();
+        let result = self.store.insert_teledata(rows, self.unique_key.as_deref()).await;
+        let latency = started.elapsed();
+
+        self.stats.batches += 1;
+        self.stats.total_latency += latency;
+        self.stats.max_latency = self.stats.max_latency.max(latency);
+        if result.is_ok() {
+            self.stats.rows += rows.len() as u64;
+            log::debug!("Database writer {}: {} rows stored in {} ms", self.name, rows.len(), latency.as_millis());
+        }
+        result
+    }
+
+    async fn flush(&mut self, batch: &mut Vec<TeleData>) {
+        if batch.is_empty() {
+            return;
+        }
+
+        // Keep the order: nothing bypasses rows already in the journal
+        if !self.journal.is_empty() {
+            self.journal_rows(batch.drain(..));
+            return;
+        }
+
+        match self.insert(batch).await {
+            Ok(()) => {}
+            Err(e) if e.is_retryable() => {
+                log::warn!("Database writer {}: {} rows journaled: {}", self.name, batch.len(), e);
+                self.journal_rows(batch.drain(..));
+            }
+            Err(e) => self.reject(batch.len(), &e),
+        }
+        batch.clear();
+    }
+
+    /// Send the journal in order, stops at the first failure
+    async fn replay(&mut self) {
+        let pending = self.journal.len();
+        while !self.journal.is_empty() {
+            let mut rows = Vec::with_capacity(self.batch_size);
+            while rows.len() < self.batch_size {
+                match self.journal.pop_front() {
+                    Some(row) => rows.push(row),
+                    None => break,
+                }
+            }
+
+            match self.insert(&rows).await {
+                Ok(()) => {}
+                Err(e) if e.is_retryable() => {
+                    log::warn!(
+                        "Database writer {}: journal replay failed, {} rows pending: {}",
+                        self.name,
+                        pending,
+                        e
+                    );
+                    for row in rows.into_iter().rev() {
+                        self.journal.push_front(row);
+                    }
+                    break;
+                }
+                // Sending these rows again would fail the same way
+                Err(e) => self.reject(rows.len(), &e),
+            }
+        }
+
+        if let Err(e) = self.journal.compact() {
+            log::error!("Database writer {}: journal error: {}", self.name, e);
+        }
+        let sent = pending - self.journal.len().min(pending);
+        if sent > 0 {
+            log::info!("Database writer {}: {} journaled rows sent", self.name, sent);
+        }
+    }
+
+    fn reject(&mut self, rows: usize, e: &DbError) {
+        self.stats.rejected_rows += rows as u64;
+        log::error!("Database writer {}: {} rows rejected: {}", self.name, rows, e);
+    }
+
+    fn journal_rows(&mut self, rows: impl Iterator<Item = TeleData>) {
+        for row in rows {
+            self.stats.journaled_rows += 1;
+            if let Err(e) = self.journal.push(row) {
+                log::error!("Database writer {}: journal error, row lost: {}", self.name, e);
+            }
+        }
+    }
+
+    fn report(&mut self, queued: usize) {
+        let stats = std::mem::take(&mut self.stats);
+        if stats.batches > 0 {
+            log::info!(
+                "Database writer {}: {} rows in {} batches, {} journaled, {} rejected, latency avg {} ms max {} ms, {} queued, {} in journal",
+                self.name,
+                stats.rows,
+                stats.batches,
+                stats.journaled_rows,
+                stats.rejected_rows,
+                (stats.total_latency / stats.batches as u32).as_millis(),
+                stats.max_latency.as_millis(),
+                queued,
+                self.journal.len()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        Up,
+        Down,
+        Rejecting,
+    }
+
+    /// In-memory store keeping the values of every stored batch
+    struct FakeStore {
+        state: Mutex<State>,
+        batches: Mutex<Vec<Vec<f64>>>,
+    }
+
+    impl FakeStore {
+        fn new() -> Arc<Self> {
+            Arc::new(Self {
+                state: Mutex::new(State::Up),
+                batches: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn set(&self, state: State) {
+            *self.state.lock().unwrap() = state;
+        }
+
+        fn batches(&self) -> Vec<Vec<f64>> {
+            self.batches.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl TeleStore for FakeStore {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        async fn insert_teledata(&self, rows: &[TeleData], _unique_key: Option<&str>) -> Result<(), DbError> {
+            match *self.state.lock().unwrap() {
+                State::Up => {
+                    self.batches.lock().unwrap().push(rows.iter().map(|r| r.td_dev_value).collect());
+                    Ok(())
+                }
+                State::Down => Err(DbError::Unavailable("store down".to_string())),
+                State::Rejecting => Err(DbError::Decode("bad rows".to_string())),
+            }
+        }
+    }
+
+    fn writer(store: Arc<FakeStore>) -> Writer {
+        Writer {
+            name: "fake",
+            store,
+            journal: Spool::open(None, 100, None).unwrap(),
+            batch_size: 2,
+            unique_key: None,
+            stats: WriterStats::default(),
+        }
+    }
+
+    fn rows(values: &[f64]) -> Vec<TeleData> {
+        values
+            .iter()
+            .map(|v| TeleData::new(2, 64, "t1", 0, *v, 0, None))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn journal_keeps_the_order() {
+        let store = FakeStore::new();
+        let mut writer = writer(store.clone());
+
+        store.set(State::Down);
+        writer.flush(&mut rows(&[1.0, 2.0])).await;
+        assert_eq!(writer.journal.len(), 2);
+
+        // New rows queue behind the journal even when the store is back
+        store.set(State::Up);
+        writer.flush(&mut rows(&[3.0])).await;
+        assert!(store.batches().is_empty());
+        assert_eq!(writer.journal.len(), 3);
+
+        writer.replay().await;
+        assert_eq!(store.batches(), [vec![1.0, 2.0], vec![3.0]]);
+        assert!(writer.journal.is_empty());
+
+        writer.flush(&mut rows(&[4.0])).await;
+        assert_eq!(store.batches().last(), Some(&vec![4.0]));
+    }
+
+    #[tokio::test]
+    async fn failed_replay_keeps_the_journal() {
+        let store = FakeStore::new();
+        let mut writer = writer(store.clone());
+
+        store.set(State::Down);
+        writer.flush(&mut rows(&[1.0, 2.0, 3.0])).await;
+        writer.replay().await;
+        writer.flush(&mut rows(&[4.0])).await;
+        writer.replay().await;
+        assert_eq!(writer.journal.len(), 4);
+
+        store.set(State::Up);
+        writer.replay().await;
+        assert_eq!(store.batches(), [vec![1.0, 2.0], vec![3.0, 4.0]]);
+        assert!(writer.journal.is_empty());
+    }
+
+    #[tokio::test]
+    async fn permanent_errors_are_rejected() {
+        let store = FakeStore::new();
+        let mut writer = writer(store.clone());
+
+        store.set(State::Rejecting);
+        writer.flush(&mut rows(&[1.0, 2.0])).await;
+        assert!(writer.journal.is_empty());
+        assert_eq!(writer.stats.rejected_rows, 2);
+
+        // Journaled rows failing for good are dropped too
+        store.set(State::Down);
+        writer.flush(&mut rows(&[3.0])).await;
+        store.set(State::Rejecting);
+        writer.replay().await;
+        assert!(writer.journal.is_empty());
+        assert_eq!(writer.stats.rejected_rows, 3);
+        assert!(store.batches().is_empty());
+    }
+
+    #[tokio::test]
+    async fn every_store_gets_the_rows() {
+        let (first, second) = (FakeStore::new(), FakeStore::new());
+        second.set(State::Down);
+        let writer = DbWriter::start(vec![first.clone(), second.clone()], None).unwrap();
+        for row in rows(&[1.0, 2.0, 3.0]) {
+            writer.write(row).await.unwrap();
+        }
+        writer.close().await;
+        assert_eq!(first.batches().concat(), [1.0, 2.0, 3.0]);
+        assert!(second.batches().is_empty());
+    }
+
+    #[test]
+    fn store_journal_names() {
+        assert_eq!(store_journal("teledata_journal.jsonl", "influx"), "teledata_journal.influx.jsonl");
+        assert_eq!(store_journal("/var/lib/ssn/journal", "influx"), "/var/lib/ssn/journal.influx");
+        assert_eq!(store_journal("./journal", "influx"), "./journal.influx");
+    }
+}
@@ -0,0 +1,202 @@
+// ============================================================================
+// src/homeassistant.rs
+// ============================================================================
+use crate::config::AppConfig;
+use crate::database::DeviceInfo;
+use crate::mqtt_client::SsnMqttClient;
+use crate::scale::Scale;
+use crate::sensors::ChannelInfo;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_PREFIX: &str = "homeassistant";
+
+/// One SSN (device, channel) exposed to Home Assistant
+#[derive(Debug, Clone)]
+pub struct HaEntity {
+    pub obj: u32,
+    pub device: String,
+    pub channel: u32,
+    pub name: String,
+    pub unit: Option<String>,
+    /// Values are converted by `dev_scale`, the state is read from `scaled`
+    pub scaled: bool,
+    pub writable: bool,
+    /// Local devices follow the online/offline status of this instance
+    pub local: bool,
+}
+
+impl HaEntity {
+    pub fn from_channel(obj: u32, info: &ChannelInfo) -> Self {
+        Self {
+            obj,
+            device: info.device.clone(),
+            channel: info.channel,
+            name: info.name.clone(),
+            unit: info.unit.clone(),
+            scaled: false,
+            writable: info.writable,
+            local: true,
+        }
+    }
+
+    /// Device row from the database, None if its channel is not numeric
+    pub fn from_device_info(info: &DeviceInfo, units: &BTreeMap<u32, String>) -> Option<Self> {
+        Some(Self {
+            obj: info.object,
+            device: info.device.clone(),
+            channel: info.channel.trim().parse().ok()?,
+            name: info.dev_name.clone(),
+            unit: info.dev_unit_id.and_then(|id| units.get(&id).cloned()),
+            scaled: matches!(Scale::from_device(info.dev_scale.as_deref()), Ok(Some(_))),
+            writable: false,
+            local: false,
+        })
+    }
+
+    /// Device seen on the broker only
+    pub fn observed(obj: u32, device: &str, channel: u32) -> Self {
+        Self {
+            obj,
+            device: device.to_string(),
+            channel,
+            name: format!("{} {}", device, channel),
+            unit: None,
+            scaled: false,
+            writable: false,
+            local: false,
+        }
+    }
+
+    fn key(&self) -> (u32, String, u32) {
+        (self.obj, self.device.clone(), self.channel)
+    }
+}
+
+/// Home Assistant device class and state class for a unit of measurement
+fn classes(unit: &str, name: &str) -> (Option<&'static str>, &'static str) {
+    let name = name.to_lowercase();
+    let device_class = match unit {
+        "°C" | "°F" | "K" => Some("temperature"),
+        "%" if name.contains("hum") => Some("humidity"),
+        "%" if name.contains("bat") => Some("battery"),
+        "Pa" | "hPa" | "kPa" | "mbar" | "bar" | "psi" => Some("pressure"),
+        "mV" | "V" | "kV" => Some("voltage"),
+        "mA" | "A" => Some("current"),
+        "W" | "kW" => Some("power"),
+        "Wh" | "kWh" => Some("energy"),
+        "lx" => Some("illuminance"),
+        "Hz" | "kHz" => Some("frequency"),
+        "ppm" if name.contains("co2") => Some("carbon_dioxide"),
+        _ => None,
+    };
+    let state_class = match device_class {
+        Some("energy") => "total_increasing",
+        _ => "measurement",
+    };
+    (device_class, state_class)
+}
+
+/// Device id safe for discovery topics and unique ids
+fn sanitize(device: &str) -> String {
+    device
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// Publishes retained discovery configs under `{prefix}/{component}/...` and
+/// translates Home Assistant `set` commands to SSN `/in` topics
+pub struct HaDiscovery {
+    mqtt_client: Arc<SsnMqttClient>,
+    account: u32,
+    prefix: String,
+    units: BTreeMap<u32, String>,
+    announced: Mutex<HashSet<(u32, String, u32)>>,
+}
+
+impl HaDiscovery {
+    pub fn new(account: u32, app: &AppConfig, mqtt_client: Arc<SsnMqttClient>) -> anyhow::Result<Self> {
+        mqtt_client.subscribe_commands(mqtt_client.topics().device.filter(account, "set"))?;
+        Ok(Self {
+            mqtt_client,
+            account,
+            prefix: app.ha_discovery_prefix.clone().unwrap_or_else(|| DEFAULT_PREFIX.to_string()),
+            units: app.ha_units.clone().unwrap_or_default(),
+            announced: Mutex::new(HashSet::new()),
+        })
+    }
+
+    pub fn units(&self) -> &BTreeMap<u32, String> {
+        &self.units
+    }
+
+    pub fn is_announced(&self, obj: u32, device: &str, channel: u32) -> bool {
+        self.announced
+            .lock()
+            .unwrap()
+            .contains(&(obj, device.to_string(), channel))
+    }
+
+    /// Publish the discovery config of an entity, once per run
+    pub fn announce(&self, entity: &HaEntity) -> anyhow::Result<()> {
+        if !self.announced.lock().unwrap().insert(entity.key()) {
+            return Ok(());
+        }
+
+        let topics = self.mqtt_client.topics();
+        let topic = |suffix: &str| topics.device_topic(self.account, entity.obj, &entity.device, entity.channel, suffix);
+        let node = format!("ssn_{}_{}", self.account, entity.obj);
+        let object_id = format!("{}_{}", sanitize(&entity.device), entity.channel);
+
+        let mut config = serde_json::json!({
+            "name": entity.name,
+            "unique_id": format!("{}_{}", node, object_id),
+            "object_id": format!("{}_{}", node, object_id),
+            "state_topic": topic(if entity.scaled { "scaled" } else { "out" }),
+            "device": {
+                "identifiers": [format!("{}_{}", node, sanitize(&entity.device))],
+                "name": format!("SSN {}/{} {}", self.account, entity.obj, entity.device),
+                "manufacturer": "SSN",
+            },
+        });
+        if entity.local {
+            config["availability_topic"] = self.mqtt_client.status_topic().into();
+        }
+
+        let component = if entity.writable {
+            config["command_topic"] = topic("set").into();
+            config["payload_on"] = "ON".into();
+            config["payload_off"] = "OFF".into();
+            config["state_on"] = "1".into();
+            config["state_off"] = "0".into();
+            "switch"
+        } else {
+            if let Some(unit) = &entity.unit {
+                let (device_class, state_class) = classes(unit, &entity.name);
+                config["unit_of_measurement"] = unit.as_str().into();
+                config["state_class"] = state_class.into();
+                if let Some(device_class) = device_class {
+                    config["device_class"] = device_class.into();
+                }
+            }
+            "sensor"
+        };
+
+        let config_topic = format!("{}/{}/{}/{}/config", self.prefix, component, node, object_id);
+        log::debug!("Home Assistant discovery: {}", config_topic);
+        self.mqtt_client.publish_retained(config_topic, config.to_string())
+    }
+
+    /// Translate a `set` command from Home Assistant to the SSN `/in` topic
+    pub fn command(&self, obj: u32, device: &str, channel: u32, payload: &str) -> anyhow::Result<()> {
+        let value = match payload.trim() {
+            "ON" | "on" => 1.0,
+            "OFF" | "off" => 0.0,
+            v => v
+                .parse()
+                .map_err(|_| anyhow::anyhow!("unsupported Home Assistant command '{}'", v))?,
+        };
+        self.mqtt_client.publish_device_command(obj, device, channel, value)
+    }
+}
@@ -0,0 +1,144 @@
+// ============================================================================
+// src/influx.rs
+// ============================================================================
+use crate::config::InfluxConfig;
+use crate::database::{query_value, CachedInfo, DatabaseClient, DbError, TeleData};
+use crate::store::TeleStore;
+use async_trait::async_trait;
+use std::fmt::Write;
+use std::sync::Arc;
+
+const DEFAULT_MEASUREMENT: &str = "ssn";
+
+/// InfluxDB sink: rows are written in line protocol, one measurement per
+/// device group (`dev_grp` of the devices table), e.g.
+/// `heating,account=2,object=64,device=t1,channel=0 value=21.5 1700000000`
+pub struct InfluxStore {
+    client: reqwest::Client,
+    write_url: String,
+    config: InfluxConfig,
+    /// Device groups are looked up here when set
+    devices: Option<Arc<DatabaseClient>>,
+}
+
+impl InfluxStore {
+    pub fn new(config: &InfluxConfig, devices: Option<Arc<DatabaseClient>>) -> anyhow::Result<Self> {
+        let base = config.url.trim_end_matches('/');
+        let write_url = match (&config.bucket, &config.database) {
+            (Some(bucket), _) => format!(
+                "{}/api/v2/write?org={}&bucket={}&precision=s",
+                base,
+                query_value(config.org.as_deref().unwrap_or_default()),
+                query_value(bucket)
+            ),
+            (None, Some(database)) => format!("{}/write?db={}&precision=s", base, query_value(database)),
+            (None, None) => anyhow::bail!("persist.influx: bucket or database is required"),
+        };
+        Ok(Self {
+            client: crate::database::http_client()?,
+            write_url,
+            config: config.clone(),
+            devices,
+        })
+    }
+
+    /// Measurement of a row from the cached device description, never
+    /// waits for the database. A device not looked up yet fails the batch,
+    /// which is then journaled, so that its points do not go to another
+    /// measurement than its `dev_grp`.
+    fn measurement(&self, row: &TeleData) -> Result<String, DbError> {
+        let group = match self.devices {
+            Some(ref db) => match db.cached_device_info(row.td_account, row.td_object, &row.td_device, row.td_channel) {
+                CachedInfo::Known(info) => info.and_then(|info| info.dev_grp).filter(|g| !g.trim().is_empty()),
+                CachedInfo::Pending => {
+                    return Err(DbError::Unavailable(format!(
+                        "device group of {} not loaded yet",
+                        row.td_device
+                    )))
+                }
+            },
+            None => None,
+        };
+        Ok(group
+            .or_else(|| self.config.measurement.clone())
+            .unwrap_or_else(|| DEFAULT_MEASUREMENT.to_string()))
+    }
+
+    /// Line protocol has no NaN or infinity, such rows are skipped
+    fn lines(&self, rows: &[TeleData]) -> Result<(String, usize), DbError> {
+        let mut lines = String::new();
+        let mut points = 0;
+        for row in rows {
+            if !row.td_dev_value.is_finite() {
+                log::warn!("InfluxDB: value {} of {}/{} skipped", row.td_dev_value, row.td_device, row.td_channel);
+                continue;
+            }
+            points += 1;
+            let _ = writeln!(
+                lines,
+                "{},account={},object={},device={},channel={} value={} {}",
+                escape(&self.measurement(row)?, ", "),
+                row.td_account,
+                row.td_object,
+                escape(&row.td_device, ",= "),
+                row.td_channel,
+                field_value(row.td_dev_value),
+                row.td_dev_ts
+            );
+        }
+        Ok((lines, points))
+    }
+}
+
+/// Backslash the characters special in this part of a line
+fn escape(value: &str, special: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '\\' || special.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Float field, written with a decimal point so that the field type stays
+/// float when the first value is a whole number
+fn field_value(value: f64) -> String {
+    let text = value.to_string();
+    if text.contains(['.', 'e', 'E']) {
+        text
+    } else {
+        format!("{}.0", text)
+    }
+}
+
+#[async_trait]
+impl TeleStore for InfluxStore {
+    fn name(&self) -> &'static str {
+        "influx"
+    }
+
+    /// Points with the same series and timestamp overwrite each other, so
+    /// `unique_key` is not needed here
+    async fn insert_teledata(&self, rows: &[TeleData], _unique_key: Option<&str>) -> Result<(), DbError> {
+        let (body, points) = self.lines(rows)?;
+        if points == 0 {
+            return Ok(());
+        }
+        let mut request = self
+            .client
+            .post(&self.write_url)
+            .header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(body);
+        if let Some(ref token) = self.config.token {
+            request = request.header(reqwest::header::AUTHORIZATION, format!("Token {}", token));
+        } else if let Some(ref user) = self.config.user {
+            request = request.basic_auth(user, self.config.pass.as_ref());
+        }
+
+        crate::database::check(request.send().await?).await?;
+        log::debug!("InfluxDB: {} points written", points);
+        Ok(())
+    }
+}
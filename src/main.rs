@@ -1,15 +1,30 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::sync::Arc;
+use std::time::Duration;
 
 // ============================================================================
 // src/main.rs
 // ============================================================================
+use crate::database::{CachedInfo, DeviceInfo, TeleData};
+use crate::topics::Topic;
 use log::LevelFilter;
-use tokio::sync::mpsc;
-use rumqttc::{Event, Packet};
 use clap::Parser;
+mod cli;
 mod config;
 mod database;
+mod db_writer;
+mod homeassistant;
+mod influx;
 mod mqtt_client;
+mod mqtt_log;
+mod mqtt_tls;
+mod payload;
+mod persist_filter;
+mod scale;
+mod sensors;
+mod spool;
+mod store;
+mod topics;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -21,6 +36,9 @@ struct Args {
     /// Log level (debug, info, warn, error)
     #[clap(short = 'l', long = "log-level", default_value = "info")]
     log_level: String,
+
+    #[command(subcommand)]
+    command: Option<crate::cli::Command>,
 }
 
 #[tokio::main]
@@ -36,133 +54,467 @@ async fn main() -> anyhow::Result<()> {
         _ => LevelFilter::Info,
     };
 
-    env_logger::Builder::from_default_env()
-        .filter_level(log_level)
-        .init();
+    crate::mqtt_log::init(log_level)?;
 
     log::info!("Using config file: {}", args.config);
     let config = crate::config::load_config(&args.config)?;
 
+    if let Some(command) = args.command {
+        return crate::cli::run(command, &config).await;
+    }
+
     log::info!("Starting SSN IoT System: {}", config.app.name);
     log::info!("Account: {}", config.ssn.account);
 
     // Initialize database client
-    let db_client = if let Some(url) = &config.app.postgrest_url {
-        Some(Arc::new(crate::database::DatabaseClient::new(url.clone())))
-    } else {
-        None
-    };
+    let db_client = config
+        .app
+        .postgrest_url
+        .as_ref()
+        .map(|url| crate::database::DatabaseClient::new(url.clone(), &config.app).map(Arc::new))
+        .transpose()?;
+
+    let stores = crate::store::open(config.persist.as_ref(), db_client.clone()).await?;
+    let db_writer = (!stores.is_empty())
+        .then(|| crate::db_writer::DbWriter::start(stores, config.persist.as_ref()))
+        .transpose()?;
 
     // Initialize MQTT client
-    let (mqtt_client, mut eventloop) = crate::mqtt_client::SsnMqttClient::new(
+    let status_obj = config.app.obj.or(config.sensors.as_ref().map(|s| s.obj)).unwrap_or(0);
+    let mut mqtt_client = crate::mqtt_client::SsnMqttClient::new(
         config.ssn.account,
-        &config.app.mqtt_host,
-        config.app.mqtt_port,
+        status_obj,
+        &config.app,
         &format!("{}persist", config.app.mqtt_broker_client_id),
-        &config.app.mqtt_broker_user,
-        &config.app.mqtt_broker_pass,
     )
     .await?;
+    if config.app.mqtt_reconnect_min.is_some() || config.app.mqtt_reconnect_max.is_some() {
+        mqtt_client.set_reconnect_delay(
+            Duration::from_secs(config.app.mqtt_reconnect_min.unwrap_or(1)),
+            Duration::from_secs(config.app.mqtt_reconnect_max.unwrap_or(60)),
+        );
+    }
 
     let mqtt_client = Arc::new(mqtt_client);
 
-    // Subscribe to topics
-    mqtt_client.subscribe_topics().await?;
+    if config.app.log_to_mqtt.unwrap_or(0) == 1 {
+        let level = config
+            .app
+            .log_to_mqtt_level
+            .as_deref()
+            .unwrap_or("warn")
+            .parse()
+            .unwrap_or(LevelFilter::Warn);
+        crate::mqtt_log::start(mqtt_client.clone(), level, config.app.log_to_mqtt_rate);
+    }
+
+    // Connect, subscriptions are restored by the client on every connect
+    let mut incoming = mqtt_client.start();
+    let mut mqtt_state = mqtt_client.watch_state();
+    let state_client = mqtt_client.clone();
+    tokio::spawn(async move {
+        while mqtt_state.changed().await.is_ok() {
+            let state = *mqtt_state.borrow_and_update();
+            let (queued, dropped) = state_client.spool_stats();
+            log::info!("MQTT connection state: {:?} (spool: {} queued, {} dropped)", state, queued, dropped);
+        }
+    });
+
+    // Start local sensor drivers
+    let sensors = config
+        .sensors
+        .as_ref()
+        .map(|sensors| crate::sensors::SensorManager::start(sensors, mqtt_client.clone()));
+    if let Some(ref sensors) = sensors {
+        for ch in sensors.channels() {
+            log::info!(
+                "Sensor channel {}/{} '{}' unit={} writable={}",
+                ch.device, ch.channel, ch.name, ch.unit.as_deref().unwrap_or("-"), ch.writable
+            );
+        }
+    }
+
+    // Home Assistant discovery: local channels, known devices, then devices seen on the broker
+    let ha = if config.app.ha_discovery.unwrap_or(0) == 1 {
+        let ha = Arc::new(crate::homeassistant::HaDiscovery::new(
+            config.ssn.account,
+            &config.app,
+            mqtt_client.clone(),
+        )?);
+        if let Some(ref sensors) = sensors {
+            for ch in sensors.channels() {
+                let entity = crate::homeassistant::HaEntity::from_channel(sensors.obj(), ch);
+                if let Err(e) = ha.announce(&entity) {
+                    log::error!("Home Assistant discovery error: {}", e);
+                }
+            }
+        }
+        Some(ha)
+    } else {
+        None
+    };
+
+    // Fill the device cache, known devices are announced to Home Assistant
+    if let Some(ref db) = db_client {
+        let (ha, db, account) = (ha.clone(), db.clone(), config.ssn.account);
+        tokio::spawn(async move {
+            match db.preload_devices(account).await {
+                Ok(devices) => {
+                    let Some(ha) = ha else {
+                        return;
+                    };
+                    for info in &devices {
+                        let Some(entity) = crate::homeassistant::HaEntity::from_device_info(info, ha.units()) else {
+                            continue;
+                        };
+                        if let Err(e) = ha.announce(&entity) {
+                            log::error!("Home Assistant discovery error: {}", e);
+                        }
+                    }
+                }
+                Err(e) => log::error!("Device cache: devices query error: {}", e),
+            }
+        });
+    }
 
     log::info!("System started successfully");
 
+    let mut ingest = Ingest {
+        mqtt_client: mqtt_client.clone(),
+        db: db_client.clone(),
+        writer: db_writer,
+        filter: crate::persist_filter::PersistFilter::new(config.persist.as_ref()),
+        units: config.app.ha_units.clone().unwrap_or_default(),
+        held: HashMap::new(),
+    };
+    let mut release = tokio::time::interval(HELD_VALUES_CHECK);
+
+    // Publishers send a value on both `out` and `out_json`, only one of them is stored
+    let json_source = match config.persist.as_ref().and_then(|p| p.source.as_deref()).unwrap_or("out") {
+        "out" => false,
+        "out_json" => true,
+        other => anyhow::bail!("unknown persist.source '{}'", other),
+    };
+    // Last retained payload per topic: the broker replays it on every
+    // subscribe, it is stored only the first time in this run
+    let mut retained: HashMap<String, bytes::Bytes> = HashMap::new();
+
     // Main event loop
     loop {
-        match eventloop.poll().await {
-            Ok(Event::Incoming(Packet::Publish(p))) => {
-                let topic = &p.topic;
-                let payload = String::from_utf8_lossy(&p.payload);
-
-                log::debug!("Received: {} -> {}", topic, payload);
-
-                // Parse topic and handle message
-                if let Some((account, obj, device, channel)) = parse_topic(&topic) {
-                    log::info!("handle message from topic {}", topic);
-                    if account == config.ssn.account {
-                        if let Ok(value) = payload.parse::<f64>() {
-                            let ts = chrono::Utc::now().timestamp();
-
-                            // Store to database
-                            if let Some(ref db) = db_client {
-                                if let Err(e) = db.set_device_value(
-                                    account, obj, &device, channel, value, 0, Some(ts)
-                                ).await {
-                                    log::error!("Database error: {}", e);
-                                }
-                            }
+        let p = tokio::select! {
+            p = incoming.recv() => match p {
+                Some(p) => p,
+                None => break,
+            },
+            _ = tokio::signal::ctrl_c() => {
+                log::info!("Shutting down");
+                mqtt_client.shutdown().await;
+                break;
+            }
+            _ = release.tick(), if !ingest.held.is_empty() => {
+                ingest.release().await;
+                continue;
+            }
+        };
+        let topic = &p.topic;
+        let payload = String::from_utf8_lossy(&p.payload);
+
+        log::debug!("Received: {} -> {}", topic, payload);
+
+        // Parse topic and handle message
+        let Some(parsed) = mqtt_client.topics().parse(topic) else {
+            log::debug!("Skip unknown topic {}", topic);
+            continue;
+        };
+        match parsed {
+            Topic::DeviceIn(ch) if ch.account == config.ssn.account => {
+                // Command to a local device
+                log::info!("handle message from topic {}", topic);
+                if let (Some(ref sensors), Ok(value)) = (&sensors, payload.trim().parse::<f64>()) {
+                    if ch.object == sensors.obj() {
+                        if let Err(e) = sensors.write(&ch.device, ch.channel, value).await {
+                            log::error!("Sensor write error: {}", e);
                         }
                     }
                 }
             }
-            Ok(Event::Incoming(Packet::Disconnect)) => {
-                log::warn!("MQTT connection disconnected, attempting to reconnect...");
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-                continue;
+            Topic::DeviceSet(ch) if ch.account == config.ssn.account => {
+                // Command from Home Assistant
+                if let Some(ref ha) = ha {
+                    if let Err(e) = ha.command(ch.object, &ch.device, ch.channel, &payload) {
+                        log::error!("Home Assistant command error: {}", e);
+                    }
+                }
             }
-            Ok(Event::Outgoing(rumqttc::Outgoing::Disconnect)) => {
-                log::warn!("MQTT client disconnecting, attempting to reconnect...");
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-                continue;
+            Topic::DeviceOut(_) | Topic::DeviceOutJson(_)
+                if p.retain && retained.get(topic).is_some_and(|last| *last == p.payload) =>
+            {
+                log::debug!("Skip retained message {} replayed again", topic);
+            }
+            Topic::DeviceOut(ch) if ch.account == config.ssn.account => {
+                log::info!("handle message from topic {}", topic);
+                if p.retain {
+                    retained.insert(topic.clone(), p.payload.clone());
+                }
+                if let Some(ref ha) = ha {
+                    if !ha.is_announced(ch.object, &ch.device, ch.channel) {
+                        announce_observed(ha, db_client.as_ref(), ch.account, ch.object, &ch.device, ch.channel);
+                    }
+                }
+                if json_source {
+                    continue;
+                }
+                if let Ok(value) = payload.parse::<f64>() {
+                    let ts = chrono::Utc::now().timestamp();
+                    ingest.value(ch.account, ch.object, &ch.device, ch.channel, value, 0, Some(ts), None).await;
+                }
+            }
+            Topic::DeviceOutJson(ch) if ch.account == config.ssn.account => {
+                log::info!("handle message from topic {}", topic);
+                if p.retain {
+                    retained.insert(topic.clone(), p.payload.clone());
+                }
+                let values = match crate::payload::parse_json(&p.payload) {
+                    Ok(values) => values,
+                    Err(e) => {
+                        log::warn!("Invalid JSON payload on {}: {}", topic, e);
+                        continue;
+                    }
+                };
+
+                for v in values {
+                    let device = v.device.as_deref().unwrap_or(&ch.device);
+                    let channel = v.channel.unwrap_or(ch.channel);
+                    if let Some(ref ha) = ha {
+                        if !ha.is_announced(ch.object, device, channel) {
+                            announce_observed(ha, db_client.as_ref(), ch.account, ch.object, device, channel);
+                        }
+                    }
+
+                    if !json_source {
+                        continue;
+                    }
+                    // Store to database with the device timestamp
+                    ingest
+                        .value(ch.account, ch.object, device, channel, v.value, v.action, v.timestamp, v.unit.as_deref())
+                        .await;
+                }
+            }
+            Topic::Commands { account, object } if account == config.ssn.account && object == status_obj => {
+                match crate::cli::parse_command(&payload) {
+                    Some(cmd) if cmd == crate::cli::FLUSH_DEVICE_CACHE => {
+                        if let Some(ref db) = db_client {
+                            db.flush_device_cache().await;
+                        }
+                    }
+                    _ => log::debug!("Ignored command on {}: {}", topic, payload),
+                }
+            }
+            other => log::debug!("Ignored {:?}", other),
+        }
+    }
+
+    ingest.close().await;
+
+    Ok(())
+}
+
+/// Values held back per device while its description is being looked up
+const HELD_VALUES_MAX: usize = 1000;
+/// How often held values are checked for a description which arrived
+const HELD_VALUES_CHECK: Duration = Duration::from_secs(1);
+
+/// Received device values: scaled by `dev_scale`, stored when the persist
+/// policy lets them through and published scaled with their unit next to
+/// the raw ones. Values of a device not looked up yet are held back until
+/// its description arrives, raw and scaled values are never mixed.
+struct Ingest {
+    mqtt_client: Arc<crate::mqtt_client::SsnMqttClient>,
+    db: Option<Arc<crate::database::DatabaseClient>>,
+    writer: Option<crate::db_writer::DbWriter>,
+    filter: crate::persist_filter::PersistFilter,
+    /// Unit names by `dev_unit_id`
+    units: BTreeMap<u32, String>,
+    held: HashMap<(u32, u32, String), VecDeque<Received>>,
+}
+
+/// A device value as received, with its timestamp
+struct Received {
+    account: u32,
+    obj: u32,
+    device: String,
+    channel: u32,
+    raw: f64,
+    action: u32,
+    ts: i64,
+    unit: Option<String>,
+}
+
+impl Ingest {
+    #[allow(clippy::too_many_arguments)]
+    async fn value(
+        &mut self,
+        account: u32,
+        obj: u32,
+        device: &str,
+        channel: u32,
+        raw: f64,
+        action: u32,
+        ts: Option<i64>,
+        unit: Option<&str>,
+    ) {
+        let value = Received {
+            account,
+            obj,
+            device: device.to_string(),
+            channel,
+            raw,
+            action,
+            ts: ts.unwrap_or_else(|| chrono::Utc::now().timestamp()),
+            unit: unit.map(str::to_string),
+        };
+        let key = (account, obj, device.to_string());
+        if let Some(held) = self.held.get_mut(&key) {
+            if held.len() >= HELD_VALUES_MAX {
+                if let Some(dropped) = held.pop_front() {
+                    log::warn!(
+                        "Device {}: description not loaded, held value {} of channel {} dropped",
+                        device,
+                        dropped.raw,
+                        dropped.channel
+                    );
+                }
             }
-            Ok(_) => {}
-            Err(e) => {
-                log::error!("MQTT error: {}, attempting to reconnect...", e);
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-                mqtt_client.recreate_eventloop();
-                mqtt_client.subscribe_topics().await?;
+            held.push_back(value);
+            return;
+        }
+
+        // Never waits for the database: a device not cached yet is fetched
+        // in the background, its values wait for it
+        match self.device_info(&value) {
+            CachedInfo::Known(info) => self.store(value, info).await,
+            CachedInfo::Pending => {
+                log::debug!("Device {}: values held until its description is loaded", device);
+                self.held.insert(key, VecDeque::from([value]));
+            }
+        }
+    }
+
+    fn device_info(&self, value: &Received) -> CachedInfo {
+        match self.db {
+            Some(ref db) => db.cached_device_info(value.account, value.obj, &value.device, value.channel),
+            None => CachedInfo::Known(None),
+        }
+    }
+
+    /// Store the held values of the devices looked up meanwhile, in order
+    async fn release(&mut self) {
+        let keys: Vec<_> = self.held.keys().cloned().collect();
+        for key in keys {
+            let Some(mut held) = self.held.remove(&key) else {
                 continue;
+            };
+            let count = held.len();
+            while let Some(value) = held.pop_front() {
+                match self.device_info(&value) {
+                    CachedInfo::Known(info) => self.store(value, info).await,
+                    CachedInfo::Pending => {
+                        held.push_front(value);
+                        break;
+                    }
+                }
+            }
+            if held.is_empty() {
+                log::debug!("Device {}: {} held values released", key.2, count);
+            } else {
+                self.held.insert(key, held);
+            }
+        }
+    }
+
+    async fn store(&mut self, received: Received, info: Option<DeviceInfo>) {
+        let Received {
+            account,
+            obj,
+            ref device,
+            channel,
+            raw,
+            action,
+            ts,
+            ref unit,
+        } = received;
+        let scale = info.as_ref().and_then(|info| {
+            crate::scale::Scale::from_device(info.dev_scale.as_deref())
+                .map_err(|e| log::warn!("Device {}/{}: {}", device, channel, e))
+                .ok()
+                .flatten()
+        });
+        let unit = info
+            .as_ref()
+            .and_then(|info| info.dev_unit_id)
+            .and_then(|id| self.units.get(&id).map(String::as_str))
+            .or(unit.as_deref());
+        let value = scale.as_ref().map_or(raw, |scale| scale.apply(raw));
+        if !value.is_finite() {
+            log::warn!("Device {}/{}: value {} (raw {}) is not finite, dropped", device, channel, value, raw);
+            return;
+        }
+
+        // Values triggered by an action are always stored
+        let store = action != 0 || self.filter.accept(account, obj, device, channel, value, ts, info.as_ref());
+        if let Some(writer) = self.writer.as_ref().filter(|_| store) {
+            let row = TeleData::new(account, obj, device, channel, value, action, Some(ts));
+            if let Err(e) = writer.write(row).await {
+                log::error!("Database error: {}", e);
+            }
+        }
+
+        if scale.is_some() || unit.is_some() {
+            if let Err(e) = self.mqtt_client.publish_scaled_value(obj, device, channel, raw, value, unit, ts, action) {
+                log::error!("Scaled value publish error: {}", e);
             }
         }
     }
-        // match eventloop.poll().await {
-        //     Ok(Event::Incoming(Packet::Publish(p))) => {
-        //         let topic = &p.topic;
-        //         let payload = String::from_utf8_lossy(&p.payload);
-                
-        //         log::debug!("Received: {} -> {}", topic, payload);
-
-        //         // Parse topic and handle message
-        //         if let Some((account, obj, device, channel)) = parse_topic(&topic) {
-        //             log::info!("handle message from topic {}", topic);
-        //             if account == config.ssn.account {
-        //                 if let Ok(value) = payload.parse::<f64>() {
-        //                     let ts = chrono::Utc::now().timestamp();
-                            
-        //                     // Store to database
-        //                     if let Some(ref db) = db_client {
-        //                         if let Err(e) = db.set_device_value(
-        //                             account, obj, &device, channel, value, 0, Some(ts)
-        //                         ).await {
-        //                             log::error!("Database error: {}", e);
-        //                         }
-        //                     }
-        //                 }
-        //             }
-        //         }
-        //     }
-        //     Ok(_) => {}
-        //     Err(e) => {
-        //         log::error!("MQTT error: {}", e);
-        //         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-        //     }
-        // }
-    // Ok(())
+
+    /// Stop the writer after storing the pending rows, values still held
+    /// are lost
+    async fn close(self) {
+        let held: usize = self.held.values().map(VecDeque::len).sum();
+        if held > 0 {
+            log::warn!("{} values dropped, their device descriptions were not loaded", held);
+        }
+        if let Some(writer) = self.writer {
+            writer.close().await;
+        }
     }
+}
 
-fn parse_topic(topic: &str) -> Option<(u32, u32, String, u32)> {
-    let parts: Vec<&str> = topic.split('/').collect();
-    if parts.len() >= 8 && parts[1] == "ssn" && parts[2] == "acc" {
-        let account = parts[3].parse().ok()?;
-        let obj = parts[5].parse().ok()?;
-        let device = parts[7].to_string();
-        let channel = parts[8].parse().ok()?;
-        Some((account, obj, device, channel))
-    } else {
-        None
+/// Announce a device first seen on the broker, with its database description
+/// if any. Never waits for the database: a device not looked up yet is
+/// announced with one of its next values.
+fn announce_observed(
+    ha: &crate::homeassistant::HaDiscovery,
+    db: Option<&Arc<crate::database::DatabaseClient>>,
+    account: u32,
+    obj: u32,
+    device: &str,
+    channel: u32,
+) {
+    let mut entity = crate::homeassistant::HaEntity::observed(obj, device, channel);
+    if let Some(db) = db {
+        match db.cached_device_info(account, obj, device, channel) {
+            CachedInfo::Known(Some(info)) => {
+                if let Some(known) = crate::homeassistant::HaEntity::from_device_info(&info, ha.units()) {
+                    if known.channel == channel {
+                        entity = known;
+                    }
+                }
+            }
+            CachedInfo::Known(None) => {}
+            CachedInfo::Pending => return,
+        }
+    }
+    if let Err(e) = ha.announce(&entity) {
+        log::error!("Home Assistant discovery error: {}", e);
     }
 }
@@ -1,76 +1,658 @@
 // ============================================================================
 // src/mqtt_client.rs
 // ============================================================================
-use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, QoS};
-use tokio::sync::mpsc;
-use std::sync::Arc;
+use crate::config::AppConfig;
+use crate::spool::{Spool, SpooledMessage};
+use crate::topics::TopicScheme;
+use rumqttc::{
+    AsyncClient, Event, EventLoop, LastWill, MqttOptions, Outgoing, Packet, Proxy, ProxyAuth, ProxyType, Publish,
+    QoS, TlsConfiguration, Transport,
+};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, watch, Notify};
 
+const REQUEST_CAPACITY: usize = 10;
+const RECONNECT_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_MAX: Duration = Duration::from_secs(60);
+const SPOOL_MAX_SIZE: usize = 10000;
+const CONTROL_CAPACITY: usize = 16;
+const LOG_CAPACITY: usize = 64;
+/// Seconds a control message (status, heartbeat, command) may wait in its
+/// queue, older ones are dropped
+const CONTROL_MAX_AGE: i64 = 30;
+const ACK_TIMEOUT: Duration = Duration::from_secs(60);
+const HEARTBEAT_INTERVAL: u64 = 60;
+const STATUS_ONLINE: &str = "online";
+const STATUS_OFFLINE: &str = "offline";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Disconnected,
+}
+
+/// Exponential reconnect delay with jitter
+struct Backoff {
+    min: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    fn new(min: Duration, max: Duration) -> Self {
+        Self { min, max, attempt: 0 }
+    }
+
+    fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// Next delay, randomly chosen between half and full of the exponential step
+    fn next_delay(&mut self) -> Duration {
+        let step = self.min.saturating_mul(1 << self.attempt.min(16)).min(self.max);
+        self.attempt += 1;
+
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let jitter = (nanos % 1000) as f64 / 1000.0;
+        step.mul_f64(0.5 + jitter / 2.0)
+    }
+}
+
+/// QoS and retain flag used for one topic class
+#[derive(Debug, Clone, Copy)]
+struct PublishMode {
+    qos: QoS,
+    retain: bool,
+}
+
+/// Publish modes of the topic classes
+#[derive(Debug, Clone, Copy)]
+struct TopicModes {
+    out: PublishMode,
+    json: PublishMode,
+    event: PublishMode,
+    commands: PublishMode,
+}
+
+impl TopicModes {
+    fn from_config(app: &AppConfig) -> anyhow::Result<Self> {
+        let qos = app.mqtt_qos.clone().unwrap_or_default();
+        let retain = app.mqtt_retain.clone().unwrap_or_default();
+        let mode = |q: Option<u8>, r: Option<u8>| -> anyhow::Result<PublishMode> {
+            Ok(PublishMode {
+                qos: rumqttc::qos(q.unwrap_or(0)).map_err(|e| anyhow::anyhow!("MQTT_QOS: {}", e))?,
+                retain: r.unwrap_or(0) == 1,
+            })
+        };
+
+        Ok(Self {
+            out: mode(qos.out, retain.out)?,
+            json: mode(qos.json, retain.json)?,
+            event: mode(qos.event, retain.event)?,
+            commands: mode(qos.commands, retain.commands)?,
+        })
+    }
+}
+
+/// Broker options from the app config: transport, credentials and proxy
+pub fn build_options(app: &AppConfig, client_id: &str) -> anyhow::Result<MqttOptions> {
+    let transport = app.mqtt_transport.as_deref().unwrap_or("tcp").to_lowercase();
+    let ws_url = |scheme: &str| {
+        format!(
+            "{}://{}:{}{}",
+            scheme,
+            app.mqtt_host,
+            app.mqtt_port,
+            app.mqtt_ws_path.as_deref().unwrap_or("/mqtt")
+        )
+    };
+
+    let mut mqtt_opts = match transport.as_str() {
+        "tcp" => MqttOptions::new(client_id, &app.mqtt_host, app.mqtt_port),
+        "tls" => {
+            let mut opts = MqttOptions::new(client_id, &app.mqtt_host, app.mqtt_port);
+            let tls = crate::mqtt_tls::client_config(app)?;
+            opts.set_transport(Transport::tls_with_config(TlsConfiguration::Rustls(Arc::new(tls))));
+            opts
+        }
+        "ws" => {
+            let mut opts = MqttOptions::new(client_id, ws_url("ws"), app.mqtt_port);
+            opts.set_transport(Transport::Ws);
+            opts
+        }
+        "wss" => {
+            let mut opts = MqttOptions::new(client_id, ws_url("wss"), app.mqtt_port);
+            let tls = crate::mqtt_tls::client_config(app)?;
+            opts.set_transport(Transport::Wss(TlsConfiguration::Rustls(Arc::new(tls))));
+            opts
+        }
+        other => anyhow::bail!("unknown MQTT_TRANSPORT '{}'", other),
+    };
+
+    mqtt_opts.set_credentials(&app.mqtt_broker_user, &app.mqtt_broker_pass);
+    mqtt_opts.set_keep_alive(Duration::from_secs(60));
+    mqtt_opts.set_clean_session(app.mqtt_clean_session.unwrap_or(1) != 0);
+    // mqtt_opts.set_connection_timeout(10);
+
+    if let Some(host) = &app.mqtt_proxy_host {
+        let auth = match (&app.mqtt_proxy_user, &app.mqtt_proxy_pass) {
+            (Some(username), Some(password)) => ProxyAuth::Basic {
+                username: username.clone(),
+                password: password.clone(),
+            },
+            _ => ProxyAuth::None,
+        };
+        mqtt_opts.set_proxy(Proxy {
+            ty: ProxyType::Http,
+            auth,
+            addr: host.clone(),
+            port: app.mqtt_proxy_port.unwrap_or(3128),
+        });
+    }
+
+    log::info!("MQTT broker {:?} via {}", mqtt_opts.broker_address(), transport);
+    Ok(mqtt_opts)
+}
+
+/// Traffic counters reported in heartbeats
+#[derive(Default)]
+struct Counters {
+    published: AtomicU64,
+    received: AtomicU64,
+    connects: AtomicU64,
+}
+
+/// Publish of the sender task waiting for the event loop: written for
+/// QoS 0, PubAck or PubComp of its packet id for QoS 1 and 2
+struct Outstanding {
+    qos: QoS,
+    pkid: Option<u16>,
+    done: oneshot::Sender<()>,
+}
+
+/// MQTT connection manager: owns the client and its event loop,
+/// reconnects on errors and restores subscriptions after every connect.
+/// Publishes go through the spool, a sender task sends them one at a time
+/// and removes each only when the broker has it, so nothing is lost
+/// when the connection drops.
+/// The instance state is kept retained on the object `status` topic:
+/// "online" after connect, "offline" (Last Will) when the client dies.
 pub struct SsnMqttClient {
-    client: AsyncClient,
     account: u32,
-    host: String,
-    port: u16,
-    client_id: String,
-    username: String,
-    password: String,
+    topics: TopicScheme,
+    status_topic: String,
+    options: MqttOptions,
+    modes: TopicModes,
+    client: AsyncClient,
+    eventloop: tokio::sync::Mutex<Option<EventLoop>>,
+    state: watch::Sender<ConnectionState>,
+    spool: Mutex<Spool>,
+    /// Status and heartbeat publishes, sent before the spooled ones
+    control: mpsc::Sender<SpooledMessage>,
+    control_rx: tokio::sync::Mutex<Option<mpsc::Receiver<SpooledMessage>>>,
+    /// Forwarded log records, sent only when nothing else is waiting
+    logs: mpsc::Sender<SpooledMessage>,
+    logs_rx: tokio::sync::Mutex<Option<mpsc::Receiver<SpooledMessage>>>,
+    /// Wakes the sender task when a message was spooled
+    wake: Notify,
+    outstanding: Mutex<Option<Outstanding>>,
+    reconnect_min: Duration,
+    reconnect_max: Duration,
+    heartbeat: Duration,
+    counters: Counters,
+    started: chrono::DateTime<chrono::Utc>,
+    stopping: AtomicBool,
+    /// Command filters added by other modules, restored on every connect
+    extra_topics: Mutex<Vec<String>>,
 }
 
 impl SsnMqttClient {
-    pub async fn new(
-        account: u32,
-        host: &str,
-        port: u16,
-        client_id: &str,
-        username: &str,
-        password: &str,
-    ) -> anyhow::Result<(Self, EventLoop)> {
-        let mut mqtt_opts = MqttOptions::new(client_id, host, port);
-        mqtt_opts.set_credentials(username, password);
-        mqtt_opts.set_keep_alive(Duration::from_secs(60));
-        // mqtt_opts.set_connection_timeout(10);
-
-        let (client, eventloop) = AsyncClient::new(mqtt_opts, 10);
-
-        Ok((
-            Self {
-                client,
-                account,
-                host: host.to_string(),
-                port,
-                client_id: client_id.to_string(),
-                username: username.to_string(),
-                password: password.to_string(),
-            },
-            eventloop,
-        ))
+    pub async fn new(account: u32, status_obj: u32, app: &AppConfig, client_id: &str) -> anyhow::Result<Self> {
+        let topics = TopicScheme::from_config(app.mqtt_topics.as_ref())?;
+        let status_topic = topics.object_topic(account, status_obj, "status");
+        let mut mqtt_opts = build_options(app, client_id)?;
+        mqtt_opts.set_last_will(LastWill::new(&status_topic, STATUS_OFFLINE, QoS::AtLeastOnce, true));
+
+        let (client, eventloop) = AsyncClient::new(mqtt_opts.clone(), REQUEST_CAPACITY);
+        let (state, _) = watch::channel(ConnectionState::Connecting);
+        let (control, control_rx) = mpsc::channel(CONTROL_CAPACITY);
+        let (logs, logs_rx) = mpsc::channel(LOG_CAPACITY);
+        let spool = Spool::open(
+            app.mqtt_spool_file.as_deref(),
+            app.mqtt_spool_max_size.unwrap_or(SPOOL_MAX_SIZE),
+            app.mqtt_spool_max_age,
+        )?;
+
+        Ok(Self {
+            account,
+            topics,
+            status_topic,
+            options: mqtt_opts,
+            modes: TopicModes::from_config(app)?,
+            client,
+            eventloop: tokio::sync::Mutex::new(Some(eventloop)),
+            state,
+            spool: Mutex::new(spool),
+            control,
+            control_rx: tokio::sync::Mutex::new(Some(control_rx)),
+            logs,
+            logs_rx: tokio::sync::Mutex::new(Some(logs_rx)),
+            wake: Notify::new(),
+            outstanding: Mutex::new(None),
+            reconnect_min: RECONNECT_MIN,
+            reconnect_max: RECONNECT_MAX,
+            heartbeat: Duration::from_secs(app.mqtt_heartbeat.unwrap_or(HEARTBEAT_INTERVAL)),
+            counters: Counters::default(),
+            started: chrono::Utc::now(),
+            stopping: AtomicBool::new(false),
+            extra_topics: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Set the reconnect backoff limits
+    pub fn set_reconnect_delay(&mut self, min: Duration, max: Duration) {
+        self.reconnect_min = min;
+        self.reconnect_max = max.max(min);
+    }
+
+    /// Handle of the request channel of the event loop
+    fn client(&self) -> AsyncClient {
+        self.client.clone()
+    }
+
+    /// Receiver following the connection state
+    pub fn watch_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
+    fn is_connected(&self) -> bool {
+        *self.state.borrow() == ConnectionState::Connected
+    }
+
+    fn lock_spool(&self) -> std::sync::MutexGuard<'_, Spool> {
+        self.spool.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn topics(&self) -> &TopicScheme {
+        &self.topics
     }
 
-    pub fn recreate_eventloop(&self) -> EventLoop {
-        log::info!("recreate_eventloop at broker: {}", &self.host);
-        let mut mqtt_opts = MqttOptions::new(&self.client_id, &self.host, self.port);
-        mqtt_opts.set_credentials(&self.username, &self.password);
-        mqtt_opts.set_keep_alive(Duration::from_secs(60));
-        // mqtt_opts.set_connection_timeout(10);
+    pub fn status_topic(&self) -> &str {
+        &self.status_topic
+    }
+
+    /// Spooled messages count and messages dropped from the spool
+    pub fn spool_stats(&self) -> (usize, u64) {
+        let spool = self.lock_spool();
+        (spool.len(), spool.dropped())
+    }
+
+    fn set_state(&self, state: ConnectionState) {
+        self.state.send_if_modified(|current| {
+            let changed = *current != state;
+            *current = state;
+            changed
+        });
+    }
+
+    /// Spawn the connection task, incoming publishes are sent to the returned channel
+    pub fn start(self: &Arc<Self>) -> mpsc::Receiver<Publish> {
+        let (tx, rx) = mpsc::channel(100);
+        tokio::spawn(self.clone().run(tx));
+        tokio::spawn(self.clone().run_sender());
+        if !self.heartbeat.is_zero() {
+            tokio::spawn(self.clone().run_heartbeat());
+        }
+        rx
+    }
 
-        let (client, eventloop) = AsyncClient::new(mqtt_opts, 10);
-        eventloop
+    /// Publish "offline" and disconnect, the Last Will is not sent by the broker then
+    pub async fn shutdown(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        let client = self.client();
+        if self.is_connected() {
+            let _ = client
+                .publish(&self.status_topic, QoS::AtLeastOnce, true, STATUS_OFFLINE)
+                .await;
+        }
+        let _ = client.disconnect().await;
+        if let Err(e) = self.lock_spool().compact() {
+            log::error!("Spool compact error: {}", e);
+        }
+
+        // Give the connection task time to flush the requests
+        let mut state = self.watch_state();
+        let _ = tokio::time::timeout(Duration::from_secs(3), state.wait_for(|s| *s != ConnectionState::Connected)).await;
     }
 
-    pub async fn subscribe_topics(&self) -> anyhow::Result<()> {
-        let topics = vec![
-            format!("/ssn/acc/{}/obj/+/device/+/+/out", self.account),
-            format!("/ssn/acc/{}/obj/+/commands", self.account),
+    /// Queue a publish ahead of the spool, dropped if the queue is full or
+    /// it is not sent within CONTROL_MAX_AGE
+    fn publish_control(&self, topic: String, qos: QoS, retain: bool, payload: String) -> anyhow::Result<()> {
+        let msg = SpooledMessage {
+            topic,
+            payload,
+            qos: qos as u8,
+            retain,
+            ts: chrono::Utc::now().timestamp(),
+        };
+        self.control.try_send(msg)?;
+        Ok(())
+    }
+
+    /// Retained "online" status and instance info, sent after every connect
+    fn publish_birth(&self) -> anyhow::Result<()> {
+        self.publish_control(self.status_topic.clone(), QoS::AtLeastOnce, true, STATUS_ONLINE.to_string())?;
+
+        let info = serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "client_id": self.options.client_id(),
+            "started": self.started.timestamp(),
+            "uptime": (chrono::Utc::now() - self.started).num_seconds(),
+            "connects": self.counters.connects.load(Ordering::Relaxed),
+        });
+        self.publish_control(format!("{}/info", self.status_topic), QoS::AtLeastOnce, true, info.to_string())
+    }
+
+    /// Best effort publish to the account `log/ssnmqtt` topic, log records are
+    /// not spooled and are dropped while the broker is unreachable or the
+    /// log queue is full. Never blocks and never logs, it is called by the logger.
+    pub fn publish_log(&self, payload: String) -> anyhow::Result<()> {
+        if !self.is_connected() {
+            anyhow::bail!("not connected");
+        }
+        let msg = SpooledMessage {
+            topic: self.topics.account_topic(self.account, "log/ssnmqtt"),
+            payload,
+            qos: QoS::AtMostOnce as u8,
+            retain: false,
+            ts: chrono::Utc::now().timestamp(),
+        };
+        self.logs.try_send(msg)?;
+        Ok(())
+    }
+
+    /// Periodic heartbeat with uptime and traffic counters
+    async fn run_heartbeat(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(self.heartbeat);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if !self.is_connected() {
+                continue;
+            }
+
+            let (spooled, dropped) = self.spool_stats();
+            let heartbeat = serde_json::json!({
+                "ts": chrono::Utc::now().timestamp(),
+                "uptime": (chrono::Utc::now() - self.started).num_seconds(),
+                "published": self.counters.published.load(Ordering::Relaxed),
+                "received": self.counters.received.load(Ordering::Relaxed),
+                "connects": self.counters.connects.load(Ordering::Relaxed),
+                "spooled": spooled,
+                "dropped": dropped,
+            });
+            if let Err(e) = self.publish_control(
+                format!("{}/heartbeat", self.status_topic),
+                QoS::AtMostOnce,
+                false,
+                heartbeat.to_string(),
+            ) {
+                log::warn!("MQTT heartbeat error: {}", e);
+            }
+        }
+    }
+
+    async fn run(self: Arc<Self>, incoming: mpsc::Sender<Publish>) {
+        let Some(mut eventloop) = self.eventloop.lock().await.take() else {
+            log::error!("MQTT connection task is already running");
+            return;
+        };
+        let mut backoff = Backoff::new(self.reconnect_min, self.reconnect_max);
+
+        loop {
+            let error = match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(ack))) => {
+                    log::info!("MQTT connected to {:?} ({:?})", self.options.broker_address(), ack.code);
+                    backoff.reset();
+                    self.counters.connects.fetch_add(1, Ordering::Relaxed);
+                    // Queued before the sender task sees the connection, so
+                    // the status goes out ahead of the spool
+                    if let Err(e) = self.publish_birth() {
+                        log::error!("MQTT status publish error: {}", e);
+                    }
+                    self.set_state(ConnectionState::Connected);
+                    if let Err(e) = self.subscribe_topics() {
+                        log::error!("MQTT subscribe error: {}", e);
+                    }
+                    continue;
+                }
+                Ok(Event::Outgoing(Outgoing::Publish(pkid))) => {
+                    self.confirm(|o| match o.qos {
+                        QoS::AtMostOnce => true,
+                        _ => {
+                            o.pkid.get_or_insert(pkid);
+                            false
+                        }
+                    });
+                    continue;
+                }
+                Ok(Event::Incoming(Packet::PubAck(ack))) => {
+                    self.confirm(|o| o.qos == QoS::AtLeastOnce && o.pkid == Some(ack.pkid));
+                    continue;
+                }
+                Ok(Event::Incoming(Packet::PubComp(comp))) => {
+                    self.confirm(|o| o.qos == QoS::ExactlyOnce && o.pkid == Some(comp.pkid));
+                    continue;
+                }
+                Ok(Event::Incoming(Packet::Publish(p))) => {
+                    self.counters.received.fetch_add(1, Ordering::Relaxed);
+                    if incoming.send(p).await.is_err() {
+                        log::info!("MQTT incoming channel closed, stopping connection task");
+                        return;
+                    }
+                    continue;
+                }
+                Ok(Event::Incoming(Packet::Disconnect)) => "disconnected by broker".to_string(),
+                Ok(Event::Outgoing(Outgoing::Disconnect)) if self.stopping.load(Ordering::SeqCst) => {
+                    self.set_state(ConnectionState::Disconnected);
+                    log::info!("MQTT disconnected");
+                    return;
+                }
+                Ok(Event::Outgoing(Outgoing::Disconnect)) => "client disconnecting".to_string(),
+                Ok(_) => continue,
+                Err(e) => e.to_string(),
+            };
+
+            self.set_state(ConnectionState::Disconnected);
+            let delay = backoff.next_delay();
+            log::error!("MQTT error: {}, reconnecting in {:.1}s...", error, delay.as_secs_f64());
+            tokio::time::sleep(delay).await;
+
+            // The same event loop reconnects on the next poll and keeps the
+            // pending and inflight QoS 1/2 messages
+            self.set_state(ConnectionState::Connecting);
+        }
+    }
+
+    /// Queue subscriptions, called from the connection task on every ConnAck
+    fn subscribe_topics(&self) -> anyhow::Result<()> {
+        let mut topics = vec![
+            (self.topics.device.filter(self.account, "out"), self.modes.out.qos),
+            (self.topics.device.filter(self.account, "out_json"), self.modes.json.qos),
+            (self.topics.device.filter(self.account, "in"), self.modes.commands.qos),
+            (self.topics.object.filter(self.account, "commands"), self.modes.commands.qos),
         ];
+        for topic in self.extra_topics.lock().unwrap().iter() {
+            topics.push((topic.clone(), self.modes.commands.qos));
+        }
 
-        for topic in topics {
-            self.client.subscribe(&topic, QoS::AtMostOnce).await?;
-            log::info!("Subscribed to: {}", topic);
+        let client = self.client();
+        for (topic, qos) in topics {
+            // Not awaiting: the event loop is not polled while this runs
+            client.try_subscribe(&topic, qos)?;
+            log::info!("Subscribed to: {} ({:?})", topic, qos);
         }
 
         Ok(())
     }
 
+    /// Subscribe to an additional command filter, kept across reconnects
+    pub fn subscribe_commands(&self, topic: String) -> anyhow::Result<()> {
+        self.extra_topics.lock().unwrap().push(topic.clone());
+        if self.is_connected() {
+            self.client().try_subscribe(&topic, self.modes.commands.qos)?;
+            log::info!("Subscribed to: {} ({:?})", topic, self.modes.commands.qos);
+        }
+        Ok(())
+    }
+
+    /// Publish a retained configuration message (QoS 1)
+    pub fn publish_retained(&self, topic: String, payload: String) -> anyhow::Result<()> {
+        let mode = PublishMode {
+            qos: QoS::AtLeastOnce,
+            retain: true,
+        };
+        self.publish(topic, mode, payload)
+    }
+
+    /// Send a value to the `in` topic of a device channel. Commands are not
+    /// spooled, an actuator must not switch long after it was asked to:
+    /// they fail while the broker is not connected and expire in the queue.
+    pub fn publish_device_command(&self, obj: u32, device: &str, channel: u32, value: f64) -> anyhow::Result<()> {
+        if !self.is_connected() {
+            anyhow::bail!("MQTT broker not connected, command to {}/{} dropped", device, channel);
+        }
+        let topic = self.topics.device_topic(self.account, obj, device, channel, "in");
+        self.publish_control(topic, self.modes.commands.qos, self.modes.commands.retain, value.to_string())
+    }
+
+    /// Spool the message for the sender task
+    fn publish(&self, topic: String, mode: PublishMode, payload: String) -> anyhow::Result<()> {
+        let msg = SpooledMessage {
+            topic,
+            payload,
+            qos: mode.qos as u8,
+            retain: mode.retain,
+            ts: chrono::Utc::now().timestamp(),
+        };
+        self.lock_spool().push(msg)?;
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    /// Complete the outstanding publish when `matches` says the event is its
+    /// confirmation (it may update the packet id)
+    fn confirm(&self, matches: impl FnOnce(&mut Outstanding) -> bool) {
+        let mut outstanding = self.outstanding.lock().unwrap_or_else(|e| e.into_inner());
+        if outstanding.as_mut().is_some_and(matches) {
+            if let Some(o) = outstanding.take() {
+                let _ = o.done.send(());
+            }
+        }
+    }
+
+    /// Send the control messages, then the spool in order and log records
+    /// when both are empty, one publish at a time while connected
+    async fn run_sender(self: Arc<Self>) {
+        let (Some(mut control), Some(mut logs)) = (self.control_rx.lock().await.take(), self.logs_rx.lock().await.take())
+        else {
+            log::error!("MQTT sender task is already running");
+            return;
+        };
+        let mut state = self.watch_state();
+        let mut sent = 0u64;
+
+        loop {
+            if !self.is_connected() {
+                if state.changed().await.is_err() {
+                    return;
+                }
+                continue;
+            }
+
+            if let Ok(msg) = control.try_recv() {
+                self.send_control(&msg).await;
+                continue;
+            }
+
+            let next = self.lock_spool().front().map(|(id, msg)| (id, msg.clone()));
+            let Some((id, msg)) = next else {
+                if sent > 0 {
+                    let (queued, dropped) = self.spool_stats();
+                    log::debug!("Spool drained: {} sent, {} queued, {} dropped", sent, queued, dropped);
+                    sent = 0;
+                }
+                tokio::select! {
+                    biased;
+                    Some(msg) = control.recv() => {
+                        self.send_control(&msg).await;
+                    }
+                    _ = self.wake.notified() => {}
+                    _ = state.changed() => {}
+                    Some(msg) = logs.recv() => {
+                        self.send(&msg).await;
+                    }
+                }
+                continue;
+            };
+
+            if self.send(&msg).await {
+                sent += 1;
+                if let Err(e) = self.lock_spool().remove(id) {
+                    log::error!("Spool error: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Send a control message unless it waited too long for the connection
+    async fn send_control(&self, msg: &SpooledMessage) {
+        if chrono::Utc::now().timestamp() - msg.ts > CONTROL_MAX_AGE {
+            log::debug!("Control message to {} expired, dropped", msg.topic);
+            return;
+        }
+        self.send(msg).await;
+    }
+
+    /// Publish and wait until the event loop confirms it. While disconnected
+    /// the event loop keeps the request and sends it again after reconnect,
+    /// false if it was not confirmed in time while connected (to be sent again)
+    async fn send(&self, msg: &SpooledMessage) -> bool {
+        let qos = rumqttc::qos(msg.qos).unwrap_or(QoS::AtMostOnce);
+        let (done, mut confirmed) = oneshot::channel();
+        *self.outstanding.lock().unwrap_or_else(|e| e.into_inner()) = Some(Outstanding { qos, pkid: None, done });
+
+        if let Err(e) = self.client().publish(&msg.topic, qos, msg.retain, msg.payload.clone()).await {
+            log::warn!("MQTT publish to {} error: {}", msg.topic, e);
+            self.outstanding.lock().unwrap_or_else(|e| e.into_inner()).take();
+            return false;
+        }
+
+        loop {
+            match tokio::time::timeout(ACK_TIMEOUT, &mut confirmed).await {
+                Ok(result) => {
+                    if result.is_ok() {
+                        self.counters.published.fetch_add(1, Ordering::Relaxed);
+                    }
+                    return result.is_ok();
+                }
+                Err(_) if self.is_connected() => {
+                    log::warn!("MQTT publish to {} not confirmed, sending again", msg.topic);
+                    self.outstanding.lock().unwrap_or_else(|e| e.into_inner()).take();
+                    return false;
+                }
+                Err(_) => continue,
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub async fn publish_sensor_value(
         &self,
         obj: u32,
@@ -79,19 +661,15 @@ impl SsnMqttClient {
         value: f64,
         timestamp: i64,
         action_id: u32,
+        unit: Option<&str>,
     ) -> anyhow::Result<()> {
-        let topic = format!(
-            "/ssn/acc/{}/obj/{}/device/{}/{}/out",
-            self.account, obj, device, channel
-        );
+        let topic = self.topics.device_topic(self.account, obj, device, channel, "out");
 
         // Publish simple value
-        self.client
-            .publish(&topic, QoS::AtMostOnce, false, value.to_string())
-            .await?;
+        self.publish(topic, self.modes.out, value.to_string())?;
 
         // Publish JSON with full data
-        let json_data = serde_json::json!({
+        let mut json_data = serde_json::json!({
             "a": action_id,
             "d": device,
             "c": channel,
@@ -99,24 +677,51 @@ impl SsnMqttClient {
             "t": timestamp,
             "pub_ts": chrono::Utc::now().timestamp()
         });
+        if let Some(unit) = unit {
+            json_data["u"] = unit.into();
+        }
 
-        self.client
-            .publish(
-                &format!("{}_json", topic),
-                QoS::AtMostOnce,
-                false,
-                json_data.to_string(),
-            )
-            .await?;
+        let json_topic = self.topics.device_topic(self.account, obj, device, channel, "out_json");
+        self.publish(json_topic, self.modes.json, json_data.to_string())?;
 
         // Publish event if triggered by action
         if action_id > 0 {
-            let event_topic = format!("/ssn/acc/{}/obj/{}/event", self.account, obj);
-            self.client
-                .publish(&event_topic, QoS::AtMostOnce, false, json_data.to_string())
-                .await?;
+            let event_topic = self.topics.object_topic(self.account, obj, "event");
+            self.publish(event_topic, self.modes.event, json_data.to_string())?;
         }
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Publish a device value converted by its `dev_scale`: the value to
+    /// `scaled` and the value with the raw one and the unit to `scaled_json`
+    #[allow(clippy::too_many_arguments)]
+    pub fn publish_scaled_value(
+        &self,
+        obj: u32,
+        device: &str,
+        channel: u32,
+        raw: f64,
+        value: f64,
+        unit: Option<&str>,
+        timestamp: i64,
+        action_id: u32,
+    ) -> anyhow::Result<()> {
+        let topic = self.topics.device_topic(self.account, obj, device, channel, "scaled");
+        self.publish(topic, self.modes.out, value.to_string())?;
+
+        let mut json_data = serde_json::json!({
+            "a": action_id,
+            "d": device,
+            "c": channel,
+            "v": value,
+            "raw": raw,
+            "t": timestamp,
+        });
+        if let Some(unit) = unit {
+            json_data["u"] = unit.into();
+        }
+        let json_topic = self.topics.device_topic(self.account, obj, device, channel, "scaled_json");
+        self.publish(json_topic, self.modes.json, json_data.to_string())
+    }
+}
@@ -0,0 +1,134 @@
+// ============================================================================
+// src/mqtt_log.rs
+// ============================================================================
+use crate::mqtt_client::SsnMqttClient;
+use log::{LevelFilter, Log, Metadata, Record};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+const DEFAULT_RATE: u32 = 10;
+
+/// Records of these targets would loop back through the broker: the MQTT
+/// stack, the connection manager with its spool, and this module
+const LOOP_TARGETS: &[&str] = &[
+    "rumqttc",
+    concat!(env!("CARGO_CRATE_NAME"), "::mqtt_client"),
+    concat!(env!("CARGO_CRATE_NAME"), "::spool"),
+    concat!(env!("CARGO_CRATE_NAME"), "::mqtt_log"),
+];
+
+/// Forwarding state, set once the MQTT client exists
+static FORWARD: OnceLock<Forward> = OnceLock::new();
+
+struct Forward {
+    level: LevelFilter,
+    mqtt_client: Arc<SsnMqttClient>,
+    limiter: Mutex<RateLimiter>,
+}
+
+/// Token bucket, `rate` records per second with a burst of the same size
+struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+    suppressed: u64,
+}
+
+impl RateLimiter {
+    fn new(rate: u32) -> Self {
+        let rate = rate.max(1) as f64;
+        Self {
+            rate,
+            tokens: rate,
+            last: Instant::now(),
+            suppressed: 0,
+        }
+    }
+
+    /// Returns the number of records suppressed since the last allowed one,
+    /// or None if this record has to be suppressed too
+    fn allow(&mut self) -> Option<u64> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+
+        if self.tokens < 1.0 {
+            self.suppressed += 1;
+            return None;
+        }
+        self.tokens -= 1.0;
+        Some(std::mem::take(&mut self.suppressed))
+    }
+}
+
+/// env_logger wrapper which also forwards records to the broker
+struct MqttLogger {
+    inner: env_logger::Logger,
+}
+
+impl MqttLogger {
+    fn forward(&self, record: &Record) {
+        let Some(forward) = FORWARD.get() else {
+            return;
+        };
+        let target = record.target();
+        if record.level() > forward.level || LOOP_TARGETS.iter().any(|t| target.starts_with(t)) {
+            return;
+        }
+
+        let Some(suppressed) = forward.limiter.lock().unwrap().allow() else {
+            return;
+        };
+        let mut payload = serde_json::json!({
+            "level": record.level().as_str(),
+            "target": target,
+            "message": record.args().to_string(),
+            "timestamp": chrono::Utc::now().timestamp(),
+        });
+        if suppressed > 0 {
+            payload["suppressed"] = suppressed.into();
+        }
+        // Never blocks, the record is dropped if the log queue of the client
+        // is full; logging the failure would feed the loop
+        let _ = forward.mqtt_client.publish_log(payload.to_string());
+    }
+}
+
+impl Log for MqttLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata) || FORWARD.get().is_some_and(|f| metadata.level() <= f.level)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+        self.forward(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Install the logger, records go to env_logger until `start` is called
+pub fn init(level: LevelFilter) -> anyhow::Result<()> {
+    let inner = env_logger::Builder::from_default_env().filter_level(level).build();
+    log::set_max_level(inner.filter());
+    log::set_boxed_logger(Box::new(MqttLogger { inner }))?;
+    Ok(())
+}
+
+/// Start forwarding records of `level` and above, at most `rate` per second
+pub fn start(mqtt_client: Arc<SsnMqttClient>, level: LevelFilter, rate: Option<u32>) {
+    let forward = Forward {
+        level,
+        mqtt_client,
+        limiter: Mutex::new(RateLimiter::new(rate.unwrap_or(DEFAULT_RATE))),
+    };
+    if FORWARD.set(forward).is_err() {
+        return;
+    }
+    log::set_max_level(log::max_level().max(level));
+}
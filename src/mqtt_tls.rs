@@ -0,0 +1,105 @@
+// ============================================================================
+// src/mqtt_tls.rs
+// ============================================================================
+use crate::config::AppConfig;
+use std::io::BufReader;
+use std::sync::Arc;
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::crypto::{verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+
+/// Build the rustls client config from the MQTT_TLS_* settings
+pub fn client_config(app: &AppConfig) -> anyhow::Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    match &app.mqtt_tls_ca_file {
+        Some(path) => {
+            for cert in read_certs(path)? {
+                roots.add(cert)?;
+            }
+        }
+        None => {
+            for cert in rustls_native_certs::load_native_certs()? {
+                roots.add(cert)?;
+            }
+        }
+    }
+
+    let builder = ClientConfig::builder().with_root_certificates(roots);
+    let mut config = match (&app.mqtt_tls_client_cert, &app.mqtt_tls_client_key) {
+        (Some(cert), Some(key)) => builder.with_client_auth_cert(read_certs(cert)?, read_key(key)?)?,
+        (None, None) => builder.with_no_client_auth(),
+        _ => anyhow::bail!("MQTT_TLS_CLIENT_CERT and MQTT_TLS_CLIENT_KEY must be set together"),
+    };
+
+    if let Some(alpn) = &app.mqtt_tls_alpn {
+        config.alpn_protocols = alpn.iter().map(|p| p.as_bytes().to_vec()).collect();
+    }
+
+    if app.mqtt_tls_insecure.unwrap_or(0) == 1 {
+        log::warn!("MQTT TLS certificate verification is disabled");
+        let algorithms = tokio_rustls::rustls::crypto::ring::default_provider().signature_verification_algorithms;
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(NoCertificateVerification { algorithms }));
+    }
+
+    Ok(config)
+}
+
+fn read_certs(path: &str) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    let file = std::fs::File::open(path).map_err(|e| anyhow::anyhow!("{}: {}", path, e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file)).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        anyhow::bail!("{}: no certificates found", path);
+    }
+    Ok(certs)
+}
+
+fn read_key(path: &str) -> anyhow::Result<tokio_rustls::rustls::pki_types::PrivateKeyDer<'static>> {
+    let file = std::fs::File::open(path).map_err(|e| anyhow::anyhow!("{}: {}", path, e))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))?
+        .ok_or_else(|| anyhow::anyhow!("{}: no private key found", path))
+}
+
+/// Accepts any server certificate (lab brokers with self-signed
+/// certificates), handshake signatures are still checked
+#[derive(Debug)]
+struct NoCertificateVerification {
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
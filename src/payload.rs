@@ -0,0 +1,113 @@
+// ============================================================================
+// src/payload.rs
+// ============================================================================
+use serde::Deserialize;
+
+/// One value of an `out_json` payload: `{"a":0,"d":"dev","c":0,"v":21.5,"t":1700000000,"u":"°C"}`.
+/// Device and channel default to the ones of the topic.
+#[derive(Debug, Clone, Deserialize)]
+pub struct JsonValue {
+    #[serde(rename = "a", default)]
+    pub action: u32,
+    #[serde(rename = "d")]
+    pub device: Option<String>,
+    #[serde(rename = "c")]
+    pub channel: Option<u32>,
+    #[serde(rename = "v", deserialize_with = "number")]
+    pub value: f64,
+    #[serde(rename = "t")]
+    pub timestamp: Option<i64>,
+    #[serde(rename = "u")]
+    pub unit: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Number {
+    Float(f64),
+    Text(String),
+}
+
+/// Values are numbers, older publishers send them as strings
+fn number<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    match Number::deserialize(deserializer)? {
+        Number::Float(v) => Ok(v),
+        Number::Text(s) => s.trim().parse().map_err(serde::de::Error::custom),
+    }
+}
+
+/// Parse a single JSON value or an array of them. A bad element of an
+/// array is skipped with a warning, the others are kept.
+pub fn parse_json(payload: &[u8]) -> anyhow::Result<Vec<JsonValue>> {
+    match serde_json::from_slice(payload)? {
+        serde_json::Value::Array(elements) => Ok(elements
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, element)| {
+                serde_json::from_value(element)
+                    .map_err(|e| log::warn!("Invalid out_json element {}: {}", i, e))
+                    .ok()
+            })
+            .collect()),
+        value => Ok(vec![serde_json::from_value(value)?]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_value() {
+        let values = parse_json(br#"{"a":3,"d":"t1","c":2,"v":21.5,"t":1700000000,"u":"\u00b0C"}"#).unwrap();
+        assert_eq!(values.len(), 1);
+        let v = &values[0];
+        assert_eq!(v.action, 3);
+        assert_eq!(v.device.as_deref(), Some("t1"));
+        assert_eq!(v.channel, Some(2));
+        assert_eq!(v.value, 21.5);
+        assert_eq!(v.timestamp, Some(1700000000));
+        assert_eq!(v.unit.as_deref(), Some("°C"));
+    }
+
+    #[test]
+    fn topic_defaults() {
+        let values = parse_json(br#"{"v":1}"#).unwrap();
+        let v = &values[0];
+        assert_eq!(v.action, 0);
+        assert_eq!(v.device, None);
+        assert_eq!(v.channel, None);
+        assert_eq!(v.timestamp, None);
+        assert_eq!(v.unit, None);
+    }
+
+    #[test]
+    fn string_numbers() {
+        let values = parse_json(br#"[{"v":"21.5"},{"v":" -3 "},{"v":7}]"#).unwrap();
+        let values: Vec<_> = values.iter().map(|v| v.value).collect();
+        assert_eq!(values, [21.5, -3.0, 7.0]);
+    }
+
+    #[test]
+    fn batch() {
+        let values = parse_json(br#"[{"c":0,"v":1,"t":10},{"c":1,"v":2,"t":11}]"#).unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!((values[0].channel, values[0].timestamp), (Some(0), Some(10)));
+        assert_eq!((values[1].channel, values[1].timestamp), (Some(1), Some(11)));
+    }
+
+    #[test]
+    fn bad_elements_are_skipped() {
+        let values = parse_json(br#"[{"c":0,"v":1},{"c":1},{"c":2,"v":"x"},{"c":3,"v":4}]"#).unwrap();
+        let channels: Vec<_> = values.iter().map(|v| v.channel).collect();
+        assert_eq!(channels, [Some(0), Some(3)]);
+    }
+
+    #[test]
+    fn invalid_payloads() {
+        assert!(parse_json(b"21.5").is_err());
+        assert!(parse_json(br#"{"c":1}"#).is_err());
+        assert!(parse_json(br#"{"v":"x"}"#).is_err());
+        assert!(parse_json(b"{").is_err());
+    }
+}
@@ -0,0 +1,234 @@
+// ============================================================================
+// src/persist_filter.rs
+// ============================================================================
+use crate::config::{PersistConfig, PersistPolicy};
+use crate::database::DeviceInfo;
+use std::collections::HashMap;
+
+/// Last stored value of a channel
+struct Stored {
+    value: f64,
+    ts: i64,
+}
+
+/// Change-only persistence: decides which values of a channel are stored
+/// according to its policy, from `persist.policies` with the `dev_*`
+/// columns of the devices table taking precedence.
+/// `max_interval` is checked when a value arrives: a channel which goes
+/// quiet keeps its last stored value, nothing is written on a timer
+pub struct PersistFilter {
+    policies: Vec<PersistPolicy>,
+    last: HashMap<(u32, u32, String, u32), Stored>,
+}
+
+impl PersistFilter {
+    pub fn new(config: Option<&PersistConfig>) -> Self {
+        Self {
+            policies: config.and_then(|c| c.policies.clone()).unwrap_or_default(),
+            last: HashMap::new(),
+        }
+    }
+
+    fn policy(&self, obj: u32, device: &str, channel: u32, info: Option<&DeviceInfo>) -> Option<PersistPolicy> {
+        let configured = self.policies.iter().find(|p| {
+            p.obj.map_or(true, |o| o == obj)
+                && p.device.as_deref().map_or(true, |d| d == device)
+                && p.channel.map_or(true, |c| c == channel)
+        });
+
+        let Some(info) = info.filter(|i| {
+            i.dev_deadband.is_some()
+                || i.dev_deadband_percent.is_some()
+                || i.dev_min_interval.is_some()
+                || i.dev_max_interval.is_some()
+        }) else {
+            return configured.cloned();
+        };
+        let configured = configured.cloned().unwrap_or_default();
+        Some(PersistPolicy {
+            deadband: info.dev_deadband.or(configured.deadband),
+            deadband_percent: info.dev_deadband_percent.or(configured.deadband_percent),
+            min_interval: info.dev_min_interval.or(configured.min_interval),
+            max_interval: info.dev_max_interval.or(configured.max_interval),
+            ..configured
+        })
+    }
+
+    /// True if the value is to be stored, it then becomes the last stored one
+    #[allow(clippy::too_many_arguments)]
+    pub fn accept(
+        &mut self,
+        account: u32,
+        obj: u32,
+        device: &str,
+        channel: u32,
+        value: f64,
+        ts: i64,
+        info: Option<&DeviceInfo>,
+    ) -> bool {
+        let Some(policy) = self.policy(obj, device, channel, info) else {
+            return true;
+        };
+
+        let key = (account, obj, device.to_string(), channel);
+        if let Some(last) = self.last.get(&key) {
+            let elapsed = ts.saturating_sub(last.ts).max(0) as u64;
+            let heartbeat = policy.max_interval.is_some_and(|max| elapsed >= max);
+            if !heartbeat {
+                if policy.min_interval.is_some_and(|min| elapsed < min) {
+                    log::debug!("Persist filter: {}/{} {} within min_interval", device, channel, value);
+                    return false;
+                }
+
+                let change = (value - last.value).abs();
+                let changed = match (policy.deadband, policy.deadband_percent) {
+                    (None, None) => change > 0.0,
+                    (absolute, percent) => {
+                        absolute.is_some_and(|band| change > band)
+                            || percent.is_some_and(|pct| change > last.value.abs() * pct / 100.0)
+                    }
+                };
+                if !changed {
+                    log::debug!("Persist filter: {}/{} {} within deadband", device, channel, value);
+                    return false;
+                }
+            }
+        }
+
+        self.last.insert(key, Stored { value, ts });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(policy: PersistPolicy) -> PersistFilter {
+        PersistFilter {
+            policies: vec![policy],
+            last: HashMap::new(),
+        }
+    }
+
+    fn accept(filter: &mut PersistFilter, value: f64, ts: i64) -> bool {
+        filter.accept(2, 64, "t1", 0, value, ts, None)
+    }
+
+    #[test]
+    fn without_policy_all_values_are_stored() {
+        let mut filter = PersistFilter::new(None);
+        assert!(accept(&mut filter, 1.0, 0));
+        assert!(accept(&mut filter, 1.0, 1));
+    }
+
+    #[test]
+    fn without_deadband_only_changes_are_stored() {
+        let mut filter = filter(PersistPolicy::default());
+        assert!(accept(&mut filter, 1.0, 0));
+        assert!(!accept(&mut filter, 1.0, 1));
+        assert!(accept(&mut filter, 1.5, 2));
+    }
+
+    #[test]
+    fn deadband() {
+        let mut filter = filter(PersistPolicy {
+            deadband: Some(0.5),
+            ..Default::default()
+        });
+        assert!(accept(&mut filter, 20.0, 0));
+        assert!(!accept(&mut filter, 20.4, 1));
+        assert!(!accept(&mut filter, 19.5, 2));
+        assert!(accept(&mut filter, 20.6, 3));
+        // compared with the last stored value, not the last received one
+        assert!(!accept(&mut filter, 21.0, 4));
+        assert!(accept(&mut filter, 21.2, 5));
+    }
+
+    #[test]
+    fn deadband_percent() {
+        let mut filter = filter(PersistPolicy {
+            deadband_percent: Some(10.0),
+            ..Default::default()
+        });
+        assert!(accept(&mut filter, 200.0, 0));
+        assert!(!accept(&mut filter, 215.0, 1));
+        assert!(accept(&mut filter, 179.0, 2));
+        assert!(!accept(&mut filter, 195.0, 3));
+    }
+
+    #[test]
+    fn min_interval() {
+        let mut filter = filter(PersistPolicy {
+            min_interval: Some(30),
+            ..Default::default()
+        });
+        assert!(accept(&mut filter, 1.0, 0));
+        assert!(!accept(&mut filter, 2.0, 29));
+        assert!(accept(&mut filter, 2.0, 30));
+    }
+
+    #[test]
+    fn max_interval_stores_unchanged_values() {
+        let mut filter = filter(PersistPolicy {
+            deadband: Some(1.0),
+            max_interval: Some(900),
+            ..Default::default()
+        });
+        assert!(accept(&mut filter, 20.0, 0));
+        assert!(!accept(&mut filter, 20.0, 899));
+        assert!(accept(&mut filter, 20.0, 900));
+        assert!(!accept(&mut filter, 20.0, 901));
+    }
+
+    #[test]
+    fn max_interval_overrides_min_interval() {
+        let mut filter = filter(PersistPolicy {
+            min_interval: Some(600),
+            max_interval: Some(300),
+            ..Default::default()
+        });
+        assert!(accept(&mut filter, 1.0, 0));
+        assert!(!accept(&mut filter, 2.0, 100));
+        assert!(accept(&mut filter, 2.0, 300));
+    }
+
+    #[test]
+    fn device_columns_override_the_policy() {
+        let mut filter = filter(PersistPolicy {
+            deadband: Some(5.0),
+            ..Default::default()
+        });
+        let info = DeviceInfo {
+            account: 2,
+            object: 64,
+            device: "t1".to_string(),
+            channel: "0".to_string(),
+            dev_name: "t1".to_string(),
+            dev_descr: None,
+            dev_scale: None,
+            dev_unit_id: None,
+            dev_grp: None,
+            dev_deadband: Some(0.1),
+            dev_deadband_percent: None,
+            dev_min_interval: None,
+            dev_max_interval: None,
+        };
+        assert!(filter.accept(2, 64, "t1", 0, 20.0, 0, Some(&info)));
+        assert!(filter.accept(2, 64, "t1", 0, 20.2, 1, Some(&info)));
+        assert!(!filter.accept(2, 64, "t1", 0, 20.2, 2, None));
+    }
+
+    #[test]
+    fn policy_selectors() {
+        let mut filter = filter(PersistPolicy {
+            device: Some("t1".to_string()),
+            channel: Some(0),
+            ..Default::default()
+        });
+        assert!(filter.accept(2, 64, "t1", 0, 1.0, 0, None));
+        assert!(!filter.accept(2, 64, "t1", 0, 1.0, 1, None));
+        assert!(filter.accept(2, 64, "t1", 1, 1.0, 1, None));
+        assert!(filter.accept(2, 64, "t2", 0, 1.0, 1, None));
+    }
+}
@@ -0,0 +1,236 @@
+// ============================================================================
+// src/scale.rs
+// ============================================================================
+
+/// Conversion of a raw device value, from `devices.dev_scale`: either a
+/// factor (`0.1`) or an expression of the raw value `x` with `+ - * /`
+/// and parentheses (`x / 10 - 40`, `(x - 32) * 5 / 9`)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scale {
+    Factor(f64),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Raw,
+    Number(f64),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Scale {
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let text = text.trim();
+        if let Ok(factor) = text.parse::<f64>() {
+            return Ok(Scale::Factor(factor));
+        }
+
+        let mut parser = Parser {
+            text,
+            chars: text.char_indices().peekable(),
+        };
+        let expr = parser.expr()?;
+        parser.skip_spaces();
+        if let Some((pos, c)) = parser.chars.next() {
+            anyhow::bail!("scale '{}': unexpected '{}' at {}", text, c, pos);
+        }
+        Ok(Scale::Expr(expr))
+    }
+
+    /// Scale of a device row, None when it is empty or a factor of 1
+    pub fn from_device(dev_scale: Option<&str>) -> anyhow::Result<Option<Self>> {
+        let Some(text) = dev_scale.filter(|s| !s.trim().is_empty()) else {
+            return Ok(None);
+        };
+        let scale = Self::parse(text)?;
+        Ok((scale != Scale::Factor(1.0)).then_some(scale))
+    }
+
+    pub fn apply(&self, raw: f64) -> f64 {
+        match self {
+            Scale::Factor(factor) => raw * factor,
+            Scale::Expr(expr) => expr.eval(raw),
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, raw: f64) -> f64 {
+        match self {
+            Expr::Raw => raw,
+            Expr::Number(n) => *n,
+            Expr::Neg(e) => -e.eval(raw),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(raw), b.eval(raw));
+                match op {
+                    Op::Add => a + b,
+                    Op::Sub => a - b,
+                    Op::Mul => a * b,
+                    Op::Div => a / b,
+                }
+            }
+        }
+    }
+}
+
+/// Recursive descent: expr = term (('+'|'-') term)*, term = factor (('*'|'/') factor)*,
+/// factor = number | 'x' | '-' factor | '(' expr ')', numbers may have an
+/// exponent (`1e-3`)
+struct Parser<'a> {
+    text: &'a str,
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_spaces(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_spaces();
+        self.chars.peek().map(|(_, c)| *c)
+    }
+
+    fn expr(&mut self) -> anyhow::Result<Expr> {
+        let mut left = self.term()?;
+        loop {
+            let op = match self.peek() {
+                Some('+') => Op::Add,
+                Some('-') => Op::Sub,
+                _ => return Ok(left),
+            };
+            self.chars.next();
+            left = Expr::Binary(op, Box::new(left), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> anyhow::Result<Expr> {
+        let mut left = self.factor()?;
+        loop {
+            let op = match self.peek() {
+                Some('*') => Op::Mul,
+                Some('/') => Op::Div,
+                _ => return Ok(left),
+            };
+            self.chars.next();
+            left = Expr::Binary(op, Box::new(left), Box::new(self.factor()?));
+        }
+    }
+
+    fn factor(&mut self) -> anyhow::Result<Expr> {
+        match self.peek() {
+            Some('x') | Some('X') => {
+                self.chars.next();
+                Ok(Expr::Raw)
+            }
+            Some('-') => {
+                self.chars.next();
+                Ok(Expr::Neg(Box::new(self.factor()?)))
+            }
+            Some('(') => {
+                self.chars.next();
+                let expr = self.expr()?;
+                match self.peek() {
+                    Some(')') => {
+                        self.chars.next();
+                        Ok(expr)
+                    }
+                    _ => anyhow::bail!("scale '{}': missing ')'", self.text),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.chars.peek().map(|(pos, _)| *pos).unwrap_or(0);
+                let mut end = start;
+                while let Some((pos, c)) = self.chars.next_if(|(_, c)| c.is_ascii_digit() || *c == '.') {
+                    end = pos + c.len_utf8();
+                }
+                if let Some((pos, _)) = self.chars.next_if(|(_, c)| matches!(c, 'e' | 'E')) {
+                    end = pos + 1;
+                    if let Some((pos, _)) = self.chars.next_if(|(_, c)| matches!(c, '+' | '-')) {
+                        end = pos + 1;
+                    }
+                    while let Some((pos, _)) = self.chars.next_if(|(_, c)| c.is_ascii_digit()) {
+                        end = pos + 1;
+                    }
+                }
+                let number = &self.text[start..end];
+                number
+                    .parse()
+                    .map(Expr::Number)
+                    .map_err(|_| anyhow::anyhow!("scale '{}': invalid number '{}'", self.text, number))
+            }
+            Some(c) => anyhow::bail!("scale '{}': unexpected '{}'", self.text, c),
+            None => anyhow::bail!("scale '{}': unexpected end", self.text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(text: &str, raw: f64) -> f64 {
+        Scale::parse(text).unwrap().apply(raw)
+    }
+
+    #[test]
+    fn factor() {
+        assert_eq!(Scale::parse("0.1").unwrap(), Scale::Factor(0.1));
+        assert_eq!(Scale::parse(" 1e-3 ").unwrap(), Scale::Factor(0.001));
+        assert_eq!(eval("0.5", 43.0), 21.5);
+    }
+
+    #[test]
+    fn factor_one_is_no_scale() {
+        assert_eq!(Scale::from_device(Some("1")).unwrap(), None);
+        assert_eq!(Scale::from_device(Some("1.0")).unwrap(), None);
+        assert_eq!(Scale::from_device(Some(" ")).unwrap(), None);
+        assert_eq!(Scale::from_device(None).unwrap(), None);
+        assert_eq!(Scale::from_device(Some("2")).unwrap(), Some(Scale::Factor(2.0)));
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("x / 10 - 40", 615.0), 21.5);
+        assert_eq!(eval("2 + x * 3", 4.0), 14.0);
+        assert_eq!(eval("x - 2 - 3", 10.0), 5.0);
+        assert_eq!(eval("x / 2 / 5", 100.0), 10.0);
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(eval("(x - 32) * 5 / 9", 212.0), 100.0);
+        assert_eq!(eval("((x))", 7.0), 7.0);
+        assert_eq!(eval("2 * (x + 1)", 4.0), 10.0);
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(eval("-x", 3.0), -3.0);
+        assert_eq!(eval("-x * 2", 3.0), -6.0);
+        assert_eq!(eval("10 - -x", 3.0), 13.0);
+        assert_eq!(eval("-(x + 1)", 3.0), -4.0);
+    }
+
+    #[test]
+    fn exponents() {
+        assert_eq!(eval("x*1e-3", 2500.0), 2.5);
+        assert_eq!(eval("X * 2.5E+2", 2.0), 500.0);
+    }
+
+    #[test]
+    fn malformed() {
+        for text in ["x *", "(x", "1..2", "x)", "x y", "2e", "y", ""] {
+            assert!(Scale::parse(text).is_err(), "{}", text);
+        }
+    }
+}
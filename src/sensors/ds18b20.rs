@@ -0,0 +1,85 @@
+// ============================================================================
+// src/sensors/ds18b20.rs
+// ============================================================================
+use super::{ChannelInfo, SensorDriver, SensorValue};
+use crate::config::{Ds18b20Config, Ds18b20Master};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// 1-Wire bus master with DS18B20 thermometers, read through the w1 sysfs
+pub struct Ds18b20Driver {
+    master: Ds18b20Master,
+}
+
+pub fn create(section: &serde_yaml::Value) -> anyhow::Result<Vec<Box<dyn SensorDriver>>> {
+    let config: Ds18b20Config = serde_yaml::from_value(section.clone())?;
+    Ok(config
+        .masters
+        .into_iter()
+        .map(|master| Box::new(Ds18b20Driver { master }) as Box<dyn SensorDriver>)
+        .collect())
+}
+
+#[async_trait]
+impl SensorDriver for Ds18b20Driver {
+    fn name(&self) -> String {
+        format!("ds18b20 {} ({})", self.master.name, self.master.path)
+    }
+
+    async fn init(&mut self) -> anyhow::Result<()> {
+        for device in &self.master.devices {
+            // Supported by recent kernels only, the sensor default is used otherwise
+            let path = format!("{}/{}/resolution", self.master.path, device.name);
+            if let Err(e) = tokio::fs::write(&path, device.resolution.to_string()).await {
+                log::debug!("DS18B20 {} resolution not set: {}", device.id, e);
+            }
+        }
+        Ok(())
+    }
+
+    fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.master.scan_rate.max(1) as u64)
+    }
+
+    async fn poll(&mut self) -> anyhow::Result<Vec<SensorValue>> {
+        let mut values = Vec::new();
+        for device in &self.master.devices {
+            let path = format!("{}/{}/w1_slave", self.master.path, device.name);
+            match tokio::fs::read_to_string(&path).await {
+                Ok(content) => match parse_w1_slave(&content) {
+                    Some(t) => values.push(SensorValue::now(&device.id, 0, t)),
+                    None => log::warn!("DS18B20 {} bad reading: {:?}", device.id, content),
+                },
+                Err(e) => log::error!("DS18B20 {} read error ({}): {}", device.id, path, e),
+            }
+        }
+        Ok(values)
+    }
+
+    fn channels(&self) -> Vec<ChannelInfo> {
+        self.master
+            .devices
+            .iter()
+            .map(|device| ChannelInfo {
+                device: device.id.clone(),
+                channel: 0,
+                name: device.name.clone(),
+                unit: Some("°C".to_string()),
+                writable: false,
+            })
+            .collect()
+    }
+}
+
+/// Parse w1_slave content, e.g.
+/// `72 01 4b 46 7f ff 0e 10 57 : crc=57 YES`
+/// `72 01 4b 46 7f ff 0e 10 57 t=23125`
+fn parse_w1_slave(content: &str) -> Option<f64> {
+    let mut lines = content.lines();
+    if !lines.next()?.trim_end().ends_with("YES") {
+        return None;
+    }
+    let (_, t) = lines.next()?.split_once("t=")?;
+    let millis: i32 = t.trim().parse().ok()?;
+    Some(millis as f64 / 1000.0)
+}
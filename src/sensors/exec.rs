@@ -0,0 +1,226 @@
+// ============================================================================
+// src/sensors/exec.rs
+// ============================================================================
+use super::{ChannelInfo, SensorDriver, SensorValue};
+use crate::config::{ExecCommand, ExecConfig};
+use async_trait::async_trait;
+use regex::Regex;
+use std::process::Stdio;
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT: u32 = 10;
+
+/// Runs a shell command and publishes the number extracted from its output
+pub struct ExecDriver {
+    command: ExecCommand,
+    regex: Option<Regex>,
+}
+
+pub fn create(section: &serde_yaml::Value) -> anyhow::Result<Vec<Box<dyn SensorDriver>>> {
+    let config: ExecConfig = serde_yaml::from_value(section.clone())?;
+    let mut drivers: Vec<Box<dyn SensorDriver>> = Vec::new();
+    for command in config.commands {
+        // A bad entry is skipped, the other commands still run
+        let regex = match command.regex.as_deref().map(Regex::new).transpose() {
+            Ok(regex) => regex,
+            Err(e) => {
+                log::error!("exec {}: invalid regex: {}", command.id, e);
+                continue;
+            }
+        };
+        if let Some(path) = command.json_path.as_deref() {
+            if path.is_empty() || path.split('.').any(str::is_empty) {
+                log::error!("exec {}: invalid json_path '{}'", command.id, path);
+                continue;
+            }
+        }
+        drivers.push(Box::new(ExecDriver { command, regex }));
+    }
+    Ok(drivers)
+}
+
+impl ExecDriver {
+    async fn run(&self) -> anyhow::Result<String> {
+        let timeout = Duration::from_secs(self.command.timeout.unwrap_or(DEFAULT_TIMEOUT) as u64);
+        // Own process group: on timeout the whole pipeline is killed, not only sh
+        let child = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(&self.command.command)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true)
+            .spawn()?;
+        let pid = child.id();
+
+        let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+            Ok(output) => output?,
+            Err(_) => {
+                if let Some(pid) = pid {
+                    // SAFETY: plain kill(2) of the process group created above
+                    unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+                }
+                anyhow::bail!("timed out after {:?}", timeout);
+            }
+        };
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn extract(&self, output: &str) -> anyhow::Result<f64> {
+        if let Some(path) = &self.command.json_path {
+            let json: serde_json::Value = serde_json::from_str(output)?;
+            let value = json_path(&json, path)
+                .ok_or_else(|| anyhow::anyhow!("path '{}' not found", path))?;
+            return match value {
+                serde_json::Value::Number(n) => n.as_f64().ok_or_else(|| anyhow::anyhow!("bad number {}", n)),
+                serde_json::Value::String(s) => Ok(s.trim().parse()?),
+                serde_json::Value::Bool(b) => Ok(*b as u8 as f64),
+                other => anyhow::bail!("'{}' is not a number: {}", path, other),
+            };
+        }
+
+        if let Some(re) = &self.regex {
+            let caps = re
+                .captures(output)
+                .ok_or_else(|| anyhow::anyhow!("regex '{}' does not match", re))?;
+            let m = caps.get(1).or_else(|| caps.get(0)).map(|m| m.as_str()).unwrap_or_default();
+            return Ok(m.trim().parse()?);
+        }
+
+        Ok(output.trim().parse()?)
+    }
+}
+
+#[async_trait]
+impl SensorDriver for ExecDriver {
+    fn name(&self) -> String {
+        format!("exec {} ({})", self.command.id, self.command.command)
+    }
+
+    async fn init(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.command.interval.max(1) as u64)
+    }
+
+    async fn poll(&mut self) -> anyhow::Result<Vec<SensorValue>> {
+        let output = self
+            .run()
+            .await
+            .map_err(|e| anyhow::anyhow!("command '{}' {}", self.command.command, e))?;
+        let value = self
+            .extract(&output)
+            .map_err(|e| anyhow::anyhow!("no value in output {:?}: {}", output.trim(), e))?;
+
+        Ok(vec![SensorValue::now(
+            &self.command.id,
+            self.command.channel.unwrap_or(0),
+            value,
+        )])
+    }
+
+    fn channels(&self) -> Vec<ChannelInfo> {
+        vec![ChannelInfo {
+            device: self.command.id.clone(),
+            channel: self.command.channel.unwrap_or(0),
+            name: self.command.name.clone().unwrap_or_else(|| self.command.id.clone()),
+            unit: self.command.unit.clone(),
+            writable: false,
+        }]
+    }
+}
+
+/// Follow a dotted path of object keys and array indexes
+fn json_path<'a>(json: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    path.split('.')
+        .filter(|key| !key.is_empty())
+        .try_fold(json, |node, key| match node {
+            serde_json::Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => node.get(key),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn driver(command: &str, regex: Option<&str>, json_path: Option<&str>) -> ExecDriver {
+        ExecDriver {
+            command: ExecCommand {
+                id: "cmd".to_string(),
+                channel: None,
+                name: None,
+                command: command.to_string(),
+                interval: 1,
+                timeout: Some(1),
+                regex: regex.map(str::to_string),
+                json_path: json_path.map(str::to_string),
+                unit: None,
+            },
+            regex: regex.map(|re| Regex::new(re).unwrap()),
+        }
+    }
+
+    #[test]
+    fn plain_output() {
+        assert_eq!(driver("", None, None).extract(" 21.5\n").unwrap(), 21.5);
+        assert!(driver("", None, None).extract("temp 21.5").is_err());
+    }
+
+    #[test]
+    fn regex_capture_group() {
+        let exec = driver("", Some(r"temp=(-?[\d.]+)C"), None);
+        assert_eq!(exec.extract("id=7 temp=-3.5C").unwrap(), -3.5);
+        assert!(exec.extract("id=7").is_err());
+    }
+
+    #[test]
+    fn regex_whole_match() {
+        let exec = driver("", Some(r"\d+\.\d+"), None);
+        assert_eq!(exec.extract("load 0.75 1.00").unwrap(), 0.75);
+    }
+
+    #[test]
+    fn json_path_values() {
+        let output = r#"{"data":{"sensors":[{"value":1.5},{"value":"2.5","on":true,"off":false,"name":"t"}]}}"#;
+        let value = |path: &str| driver("", None, Some(path)).extract(output);
+        assert_eq!(value("data.sensors.0.value").unwrap(), 1.5);
+        assert_eq!(value("data.sensors.1.value").unwrap(), 2.5);
+        assert_eq!(value("data.sensors.1.on").unwrap(), 1.0);
+        assert_eq!(value("data.sensors.1.off").unwrap(), 0.0);
+        assert!(value("data.sensors.1.name").is_err());
+        assert!(value("data.sensors").is_err());
+        assert!(value("data.sensors.2.value").is_err());
+        assert!(value("data.missing").is_err());
+        assert!(driver("", None, Some("a")).extract("not json").is_err());
+    }
+
+    #[tokio::test]
+    async fn run_output() {
+        assert_eq!(driver("echo 42", None, None).run().await.unwrap(), "42\n");
+    }
+
+    #[tokio::test]
+    async fn run_non_zero_exit() {
+        let e = driver("echo oops >&2; exit 3", None, None).run().await.unwrap_err();
+        assert!(e.to_string().contains("oops"), "{}", e);
+    }
+
+    #[tokio::test]
+    async fn run_timeout() {
+        let started = std::time::Instant::now();
+        let e = driver("sleep 5 | cat", None, None).run().await.unwrap_err();
+        assert!(e.to_string().contains("timed out"), "{}", e);
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+}
@@ -0,0 +1,498 @@
+// ============================================================================
+// src/sensors/gpio.rs
+// ============================================================================
+use super::{ChannelInfo, SensorDriver, SensorValue};
+use crate::config::{GpioConfig, GpioPin};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const GPIO_SYSFS: &str = "/sys/class/gpio";
+const DEFAULT_POLL_MS: u64 = 10;
+const DEFAULT_COUNTER_STATE: &str = "gpio_counters.json";
+
+// Channels published for "counter" pins
+const CHANNEL_TOTAL: u32 = 0;
+const CHANNEL_RATE: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+impl Edge {
+    fn parse(edge: Option<&str>) -> Self {
+        match edge.map(|e| e.to_lowercase()).as_deref() {
+            Some("falling") => Edge::Falling,
+            Some("both") => Edge::Both,
+            _ => Edge::Rising,
+        }
+    }
+
+    fn matches(self, level: bool) -> bool {
+        match self {
+            Edge::Rising => level,
+            Edge::Falling => !level,
+            Edge::Both => true,
+        }
+    }
+}
+
+/// Debounces raw samples: a new level is accepted only after it has been
+/// read continuously for the debounce time.
+#[derive(Debug)]
+struct Debouncer {
+    debounce: Duration,
+    stable: bool,
+    candidate: bool,
+    candidate_since: Instant,
+}
+
+impl Debouncer {
+    fn new(debounce: Duration, level: bool, now: Instant) -> Self {
+        Self {
+            debounce,
+            stable: level,
+            candidate: level,
+            candidate_since: now,
+        }
+    }
+
+    /// Feed a raw sample, returns the new stable level when it changed
+    fn update(&mut self, raw: bool, now: Instant) -> Option<bool> {
+        if raw != self.candidate {
+            self.candidate = raw;
+            self.candidate_since = now;
+        }
+        if self.candidate != self.stable && now.duration_since(self.candidate_since) >= self.debounce {
+            self.stable = self.candidate;
+            return Some(self.stable);
+        }
+        None
+    }
+}
+
+struct PinState {
+    pin: GpioPin,
+    value_path: String,
+    debouncer: Option<Debouncer>,
+    edge: Edge,
+    count: u64,
+    last_count: u64,
+    published: Option<bool>,
+    last_publish: Instant,
+}
+
+impl PinState {
+    fn is_counter(&self) -> bool {
+        self.pin.pin_type == "counter"
+    }
+}
+
+pub struct GpioDriver {
+    scan_rate: Duration,
+    hart_beat_timeout: Option<Duration>,
+    poll: Duration,
+    counter_state: String,
+    pins: Vec<PinState>,
+    last_scan: Instant,
+}
+
+pub fn create(section: &serde_yaml::Value) -> anyhow::Result<Vec<Box<dyn SensorDriver>>> {
+    let config: GpioConfig = serde_yaml::from_value(section.clone())?;
+    Ok(vec![Box::new(GpioDriver::new(&config))])
+}
+
+impl GpioDriver {
+    pub fn new(config: &GpioConfig) -> Self {
+        let now = Instant::now();
+        let pins = config
+            .pins
+            .iter()
+            .map(|pin| PinState {
+                pin: pin.clone(),
+                value_path: format!("{}/gpio{}/value", GPIO_SYSFS, pin.number),
+                debouncer: None,
+                edge: Edge::parse(pin.edge.as_deref()),
+                count: 0,
+                last_count: 0,
+                published: None,
+                last_publish: now,
+            })
+            .collect();
+
+        Self {
+            scan_rate: Duration::from_secs(config.scan_rate.max(1) as u64),
+            hart_beat_timeout: config.hart_beat_timeout.map(|t| Duration::from_secs(t as u64)),
+            poll: Duration::from_millis(config.poll_ms.unwrap_or(DEFAULT_POLL_MS).max(1)),
+            counter_state: config
+                .counter_state
+                .clone()
+                .unwrap_or_else(|| DEFAULT_COUNTER_STATE.to_string()),
+            pins,
+            last_scan: now,
+        }
+    }
+
+    /// Read all input pins once and count accepted edges
+    fn sample(&mut self, now: Instant) {
+        for state in self.pins.iter_mut() {
+            let Some(debouncer) = state.debouncer.as_mut() else {
+                continue;
+            };
+            let raw = match read_level(&state.value_path) {
+                Ok(level) => level,
+                Err(e) => {
+                    log::debug!("GPIO {} read error: {}", state.pin.id, e);
+                    continue;
+                }
+            };
+            if let Some(level) = debouncer.update(raw, now) {
+                if state.pin.pin_type == "counter" && state.edge.matches(level) {
+                    state.count += 1;
+                }
+            }
+        }
+    }
+
+    /// Collect values to publish after a scan period
+    fn scan(&mut self, elapsed: Duration, now: Instant) -> Vec<SensorValue> {
+        let mut values = Vec::new();
+        let mut counters_changed = false;
+
+        for state in self.pins.iter_mut() {
+            if state.is_counter() {
+                let pulses = state.count - state.last_count;
+                let rate = pulses as f64 * 60.0 / elapsed.as_secs_f64();
+                counters_changed |= pulses > 0;
+                state.last_count = state.count;
+
+                values.push(SensorValue::now(&state.pin.id, CHANNEL_TOTAL, state.count as f64));
+                values.push(SensorValue::now(&state.pin.id, CHANNEL_RATE, rate));
+                continue;
+            }
+
+            let level = match &state.debouncer {
+                Some(debouncer) => debouncer.stable,
+                None => match read_level(&state.value_path) {
+                    Ok(level) => level,
+                    Err(e) => {
+                        log::debug!("GPIO {} read error: {}", state.pin.id, e);
+                        continue;
+                    }
+                },
+            };
+
+            // Publish on change, or periodically as a heart beat. The state
+            // is updated in published(), a failed publish is tried again
+            let heart_beat = self
+                .hart_beat_timeout
+                .is_some_and(|t| now.duration_since(state.last_publish) >= t);
+            if state.published == Some(level) && !heart_beat {
+                continue;
+            }
+
+            values.push(SensorValue::now(&state.pin.id, 0, level as u8 as f64));
+        }
+
+        if counters_changed {
+            let counters: HashMap<String, u64> = self
+                .pins
+                .iter()
+                .filter(|s| s.is_counter())
+                .map(|s| (s.pin.id.clone(), s.count))
+                .collect();
+            if let Err(e) = save_counters(&self.counter_state, &counters) {
+                log::error!("GPIO counters save error ({}): {}", self.counter_state, e);
+            }
+        }
+
+        values
+    }
+}
+
+#[async_trait]
+impl SensorDriver for GpioDriver {
+    fn name(&self) -> String {
+        format!("gpio ({} pins)", self.pins.len())
+    }
+
+    /// Export pins, set directions and restore counter totals
+    async fn init(&mut self) -> anyhow::Result<()> {
+        let saved = load_counters(&self.counter_state);
+        let now = Instant::now();
+
+        for state in self.pins.iter_mut() {
+            if let Err(e) = export_pin(&state.pin) {
+                log::error!("GPIO {} ({}) export error: {}", state.pin.id, state.pin.number, e);
+            }
+            if state.is_counter() {
+                state.count = saved.get(&state.pin.id).copied().unwrap_or(0);
+                state.last_count = state.count;
+                log::info!("GPIO counter {} restored: {}", state.pin.id, state.count);
+            }
+            if state.pin.pin_type != "out" {
+                let level = read_level(&state.value_path).unwrap_or(false);
+                let debounce = Duration::from_millis(state.pin.debounce_ms.unwrap_or(0));
+                state.debouncer = Some(Debouncer::new(debounce, level, now));
+            }
+        }
+        self.last_scan = now;
+        Ok(())
+    }
+
+    fn poll_interval(&self) -> Duration {
+        self.poll
+    }
+
+    async fn poll(&mut self) -> anyhow::Result<Vec<SensorValue>> {
+        let now = Instant::now();
+        self.sample(now);
+
+        let elapsed = now.duration_since(self.last_scan);
+        if elapsed < self.scan_rate {
+            return Ok(Vec::new());
+        }
+        self.last_scan = now;
+        Ok(self.scan(elapsed, now))
+    }
+
+    fn published(&mut self, value: &SensorValue) {
+        if let Some(state) = self
+            .pins
+            .iter_mut()
+            .find(|s| s.pin.id == value.device && !s.is_counter())
+        {
+            state.published = Some(value.value != 0.0);
+            state.last_publish = Instant::now();
+        }
+    }
+
+    async fn write(&mut self, device: &str, channel: u32, value: f64) -> anyhow::Result<()> {
+        let state = self
+            .pins
+            .iter_mut()
+            .find(|s| s.pin.id == device && s.pin.pin_type == "out")
+            .ok_or_else(|| anyhow::anyhow!("GPIO {} is not an output", device))?;
+        if channel != 0 {
+            anyhow::bail!("GPIO {} has no channel {}", device, channel);
+        }
+
+        let level = value != 0.0;
+        std::fs::write(&state.value_path, if level { "1" } else { "0" })?;
+        log::info!("GPIO {} set to {}", device, level as u8);
+
+        // Force publishing of the new state on the next scan
+        state.published = None;
+        Ok(())
+    }
+
+    fn channels(&self) -> Vec<ChannelInfo> {
+        let mut channels = Vec::new();
+        for state in &self.pins {
+            if state.is_counter() {
+                channels.push(ChannelInfo {
+                    device: state.pin.id.clone(),
+                    channel: CHANNEL_TOTAL,
+                    name: format!("{} total", state.pin.name),
+                    unit: None,
+                    writable: false,
+                });
+                channels.push(ChannelInfo {
+                    device: state.pin.id.clone(),
+                    channel: CHANNEL_RATE,
+                    name: format!("{} rate", state.pin.name),
+                    unit: Some("1/min".to_string()),
+                    writable: false,
+                });
+            } else {
+                channels.push(ChannelInfo {
+                    device: state.pin.id.clone(),
+                    channel: 0,
+                    name: state.pin.name.clone(),
+                    unit: None,
+                    writable: state.pin.pin_type == "out",
+                });
+            }
+        }
+        channels
+    }
+}
+
+fn export_pin(pin: &GpioPin) -> anyhow::Result<()> {
+    let pin_dir = format!("{}/gpio{}", GPIO_SYSFS, pin.number);
+    if !std::path::Path::new(&pin_dir).exists() {
+        std::fs::write(format!("{}/export", GPIO_SYSFS), pin.number.to_string())?;
+    }
+    let direction = if pin.pin_type == "out" { "out" } else { "in" };
+    std::fs::write(format!("{}/direction", pin_dir), direction)?;
+    Ok(())
+}
+
+fn read_level(path: &str) -> anyhow::Result<bool> {
+    let value = std::fs::read_to_string(path)?;
+    Ok(value.trim() != "0")
+}
+
+fn load_counters(path: &str) -> HashMap<String, u64> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            log::warn!("GPIO counters file {} is corrupted: {}", path, e);
+            HashMap::new()
+        }),
+        Err(_) => HashMap::new(),
+    }
+}
+
+fn save_counters(path: &str, counters: &HashMap<String, u64>) -> anyhow::Result<()> {
+    // Write to a temporary file first so a crash never leaves a truncated file
+    let tmp = format!("{}.tmp", path);
+    std::fs::write(&tmp, serde_json::to_string(counters)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn debouncer_rejects_bounces() {
+        let t0 = Instant::now();
+        let mut debouncer = Debouncer::new(ms(10), false, t0);
+        assert_eq!(debouncer.update(true, t0 + ms(1)), None);
+        assert_eq!(debouncer.update(false, t0 + ms(3)), None);
+        assert_eq!(debouncer.update(true, t0 + ms(5)), None);
+        // Stable since 5 ms only
+        assert_eq!(debouncer.update(true, t0 + ms(14)), None);
+        assert_eq!(debouncer.update(true, t0 + ms(15)), Some(true));
+        assert_eq!(debouncer.update(true, t0 + ms(30)), None);
+        // A short drop never reaches the stable level
+        assert_eq!(debouncer.update(false, t0 + ms(31)), None);
+        assert_eq!(debouncer.update(true, t0 + ms(35)), None);
+        assert_eq!(debouncer.update(true, t0 + ms(50)), None);
+        assert!(debouncer.stable);
+    }
+
+    #[test]
+    fn debouncer_without_debounce_time() {
+        let t0 = Instant::now();
+        let mut debouncer = Debouncer::new(Duration::ZERO, false, t0);
+        assert_eq!(debouncer.update(true, t0), Some(true));
+        assert_eq!(debouncer.update(false, t0), Some(false));
+    }
+
+    #[test]
+    fn edges() {
+        assert_eq!(Edge::parse(None), Edge::Rising);
+        assert_eq!(Edge::parse(Some("Falling")), Edge::Falling);
+        assert_eq!(Edge::parse(Some("both")), Edge::Both);
+        assert!(Edge::Rising.matches(true) && !Edge::Rising.matches(false));
+        assert!(!Edge::Falling.matches(true) && Edge::Falling.matches(false));
+        assert!(Edge::Both.matches(true) && Edge::Both.matches(false));
+    }
+
+    /// Counter pins reading their levels from temporary files
+    struct Counters {
+        dir: std::path::PathBuf,
+        driver: GpioDriver,
+    }
+
+    impl Counters {
+        fn new(test: &str, edges: &[&str]) -> Self {
+            let dir = std::env::temp_dir().join(format!("ssn-gpio-{}-{}", std::process::id(), test));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            let pins = edges
+                .iter()
+                .enumerate()
+                .map(|(i, edge)| GpioPin {
+                    id: edge.to_string(),
+                    gpiochip: 0,
+                    number: i as u32,
+                    pin_type: "counter".to_string(),
+                    name: edge.to_string(),
+                    comment: None,
+                    debounce_ms: None,
+                    edge: Some(edge.to_string()),
+                })
+                .collect();
+            let mut driver = GpioDriver::new(&GpioConfig {
+                scan_rate: 60,
+                hart_beat_timeout: None,
+                poll_ms: None,
+                counter_state: Some(dir.join("counters.json").to_string_lossy().into_owned()),
+                pins,
+            });
+            let now = Instant::now();
+            for state in driver.pins.iter_mut() {
+                state.value_path = dir.join(&state.pin.id).to_string_lossy().into_owned();
+                state.debouncer = Some(Debouncer::new(Duration::ZERO, false, now));
+            }
+            Self { dir, driver }
+        }
+
+        /// Set every pin to the level and sample it
+        fn level(&mut self, level: bool) {
+            for state in &self.driver.pins {
+                std::fs::write(&state.value_path, if level { "1" } else { "0" }).unwrap();
+            }
+            self.driver.sample(Instant::now());
+        }
+    }
+
+    impl Drop for Counters {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn value(values: &[SensorValue], device: &str, channel: u32) -> f64 {
+        values
+            .iter()
+            .find(|v| v.device == device && v.channel == channel)
+            .map(|v| v.value)
+            .unwrap()
+    }
+
+    #[test]
+    fn counted_edges() {
+        let mut counters = Counters::new("edges", &["rising", "falling", "both"]);
+        for _ in 0..3 {
+            counters.level(true);
+            counters.level(false);
+        }
+        counters.level(true);
+        let counts: Vec<_> = counters.driver.pins.iter().map(|s| s.count).collect();
+        assert_eq!(counts, [4, 3, 7]);
+    }
+
+    #[test]
+    fn counter_rate_and_total() {
+        let mut counters = Counters::new("rate", &["rising"]);
+        for _ in 0..5 {
+            counters.level(true);
+            counters.level(false);
+        }
+        let values = counters.driver.scan(Duration::from_secs(30), Instant::now());
+        assert_eq!(value(&values, "rising", CHANNEL_TOTAL), 5.0);
+        assert_eq!(value(&values, "rising", CHANNEL_RATE), 10.0);
+
+        // The total is kept, the rate covers the last period only
+        counters.level(true);
+        let values = counters.driver.scan(Duration::from_secs(120), Instant::now());
+        assert_eq!(value(&values, "rising", CHANNEL_TOTAL), 6.0);
+        assert_eq!(value(&values, "rising", CHANNEL_RATE), 0.5);
+        let values = counters.driver.scan(Duration::from_secs(60), Instant::now());
+        assert_eq!(value(&values, "rising", CHANNEL_RATE), 0.0);
+
+        let saved = load_counters(&counters.driver.counter_state);
+        assert_eq!(saved.get("rising"), Some(&6));
+    }
+}
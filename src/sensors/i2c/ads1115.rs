@@ -0,0 +1,152 @@
+// ============================================================================
+// src/sensors/i2c/ads1115.rs
+// ============================================================================
+use super::{I2cBus, I2cChip};
+use async_trait::async_trait;
+use std::time::Duration;
+
+const REG_CONVERSION: u8 = 0x00;
+const REG_CONFIG: u8 = 0x01;
+
+const CONFIG_OS_START: u16 = 0x8000;
+const CONFIG_MODE_SINGLE: u16 = 0x0100;
+const CONFIG_DR_128SPS: u16 = 0x0080;
+const CONFIG_COMP_DISABLE: u16 = 0x0003;
+const CONVERSION_TIME: Duration = Duration::from_millis(9);
+
+const DEFAULT_GAIN: f64 = 2.048;
+
+/// Full scale ranges and their PGA bits
+const PGA: [(f64, u16); 6] = [
+    (6.144, 0b000),
+    (4.096, 0b001),
+    (2.048, 0b010),
+    (1.024, 0b011),
+    (0.512, 0b100),
+    (0.256, 0b101),
+];
+
+/// TI ADS1115 16 bit ADC, single-ended inputs published in volts,
+/// the input number is used as channel
+pub struct Ads1115 {
+    address: u16,
+    full_scale: f64,
+    pga: u16,
+    inputs: Vec<u8>,
+}
+
+impl Ads1115 {
+    pub fn new(address: u16, gain: Option<f64>, inputs: Option<Vec<u8>>) -> anyhow::Result<Self> {
+        let full_scale = gain.unwrap_or(DEFAULT_GAIN);
+        let pga = PGA
+            .iter()
+            .find(|(fs, _)| (fs - full_scale).abs() < 1e-6)
+            .map(|(_, bits)| *bits)
+            .ok_or_else(|| anyhow::anyhow!("ADS1115 unsupported gain {}", full_scale))?;
+        let inputs = inputs.unwrap_or_else(|| vec![0, 1, 2, 3]);
+        if let Some(bad) = inputs.iter().find(|&&i| i > 3) {
+            anyhow::bail!("ADS1115 has no input {}", bad);
+        }
+        Ok(Self {
+            address,
+            full_scale,
+            pga,
+            inputs,
+        })
+    }
+
+    /// Config register value starting a single-shot conversion of an input
+    pub fn config_word(&self, input: u8) -> u16 {
+        let mux = 0b100 | input as u16;
+        CONFIG_OS_START | (mux << 12) | (self.pga << 9) | CONFIG_MODE_SINGLE | CONFIG_DR_128SPS | CONFIG_COMP_DISABLE
+    }
+
+    /// Convert the conversion register content to volts
+    pub fn decode(&self, buf: [u8; 2]) -> f64 {
+        i16::from_be_bytes(buf) as f64 * self.full_scale / 32768.0
+    }
+}
+
+#[async_trait]
+impl I2cChip for Ads1115 {
+    fn init(&mut self, bus: &mut dyn I2cBus) -> anyhow::Result<()> {
+        let mut buf = [0u8; 2];
+        bus.read_registers(self.address, REG_CONFIG, &mut buf)
+    }
+
+    async fn measure(&mut self, bus: &mut dyn I2cBus) -> anyhow::Result<Vec<(u32, f64)>> {
+        let mut values = Vec::new();
+        for &input in &self.inputs {
+            let [hi, lo] = self.config_word(input).to_be_bytes();
+            bus.write(self.address, &[REG_CONFIG, hi, lo])?;
+            tokio::time::sleep(CONVERSION_TIME).await;
+
+            let mut buf = [0u8; 2];
+            bus.read_registers(self.address, REG_CONVERSION, &mut buf)?;
+            values.push((input as u32, self.decode(buf)));
+        }
+        Ok(values)
+    }
+
+    fn channels(&self) -> Vec<(u32, String, &'static str)> {
+        self.inputs
+            .iter()
+            .map(|&input| (input as u32, format!("AIN{}", input), "V"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fake::FakeBus;
+    use super::*;
+
+    const ADDRESS: u16 = 0x48;
+
+    #[test]
+    fn config_word_single_ended() {
+        // AIN0 vs GND, ±2.048 V, single shot, 128 SPS, comparator off
+        let adc = Ads1115::new(ADDRESS, None, None).unwrap();
+        assert_eq!(adc.config_word(0), 0xc583);
+
+        let adc = Ads1115::new(ADDRESS, Some(4.096), None).unwrap();
+        assert_eq!(adc.config_word(3), 0xf383);
+    }
+
+    #[test]
+    fn decode_full_scale() {
+        let adc = Ads1115::new(ADDRESS, None, None).unwrap();
+        assert_eq!(adc.decode([0x40, 0x00]), 1.024);
+        assert_eq!(adc.decode([0x80, 0x00]), -2.048);
+        assert!((adc.decode([0x7f, 0xff]) - 2.047_937_5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn invalid_gain_and_input() {
+        assert!(Ads1115::new(ADDRESS, Some(3.3), None).is_err());
+        assert!(Ads1115::new(ADDRESS, None, Some(vec![4])).is_err());
+    }
+
+    #[tokio::test]
+    async fn measure_inputs_in_order() {
+        let mut bus = FakeBus::default()
+            .response(ADDRESS, &[0x85, 0x83])
+            .response(ADDRESS, &[0x20, 0x00])
+            .response(ADDRESS, &[0xf0, 0x00]);
+        let mut adc = Ads1115::new(ADDRESS, None, Some(vec![1, 2])).unwrap();
+        adc.init(&mut bus).unwrap();
+
+        let values = adc.measure(&mut bus).await.unwrap();
+        assert_eq!(values, vec![(1, 0.512), (2, -0.256)]);
+        assert_eq!(
+            bus.writes,
+            vec![
+                (ADDRESS, vec![REG_CONFIG]),
+                (ADDRESS, vec![REG_CONFIG, 0xd5, 0x83]),
+                (ADDRESS, vec![REG_CONVERSION]),
+                (ADDRESS, vec![REG_CONFIG, 0xe5, 0x83]),
+                (ADDRESS, vec![REG_CONVERSION]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+// ============================================================================
+// src/sensors/i2c/bme280.rs
+// ============================================================================
+use super::{I2cBus, I2cChip};
+use async_trait::async_trait;
+use std::time::Duration;
+
+const REG_CHIP_ID: u8 = 0xd0;
+const REG_CALIB_TP: u8 = 0x88;
+const REG_CALIB_H: u8 = 0xe1;
+const REG_CTRL_HUM: u8 = 0xf2;
+const REG_CTRL_MEAS: u8 = 0xf4;
+const REG_DATA: u8 = 0xf7;
+
+const CHIP_ID: u8 = 0x60;
+// Humidity, temperature and pressure oversampling x1
+const CTRL_HUM: u8 = 0x01;
+const CTRL_MEAS_FORCED: u8 = (0x01 << 5) | (0x01 << 2) | 0x01;
+const MEASURE_TIME: Duration = Duration::from_millis(10);
+
+pub const CHANNEL_TEMPERATURE: u32 = 0;
+pub const CHANNEL_HUMIDITY: u32 = 1;
+pub const CHANNEL_PRESSURE: u32 = 2;
+
+/// Factory trimming parameters
+#[derive(Debug, Clone, Default)]
+pub struct Calibration {
+    t1: f64,
+    t2: f64,
+    t3: f64,
+    p: [f64; 9],
+    h1: f64,
+    h2: f64,
+    h3: f64,
+    h4: f64,
+    h5: f64,
+    h6: f64,
+}
+
+impl Calibration {
+    /// Parse the 0x88..0xa1 (26 bytes) and 0xe1..0xe7 (7 bytes) register blocks
+    pub fn parse(tp: &[u8; 26], h: &[u8; 7]) -> Self {
+        let u16_at = |i: usize| u16::from_le_bytes([tp[i], tp[i + 1]]) as f64;
+        let i16_at = |i: usize| i16::from_le_bytes([tp[i], tp[i + 1]]) as f64;
+
+        let mut p = [0.0; 9];
+        p[0] = u16_at(6);
+        for (n, p) in p.iter_mut().enumerate().skip(1) {
+            *p = i16_at(6 + n * 2);
+        }
+
+        Self {
+            t1: u16_at(0),
+            t2: i16_at(2),
+            t3: i16_at(4),
+            p,
+            h1: tp[25] as f64,
+            h2: i16::from_le_bytes([h[0], h[1]]) as f64,
+            h3: h[2] as f64,
+            h4: (((h[3] as i8 as i16) << 4) | (h[4] & 0x0f) as i16) as f64,
+            h5: (((h[5] as i8 as i16) << 4) | (h[4] >> 4) as i16) as f64,
+            h6: h[6] as i8 as f64,
+        }
+    }
+
+    /// Compensate a raw 8 byte data block (0xf7..0xfe), returns
+    /// temperature (°C), humidity (%) and pressure (hPa)
+    pub fn compensate(&self, data: &[u8; 8]) -> (f64, f64, f64) {
+        let adc_p = ((data[0] as u32) << 12 | (data[1] as u32) << 4 | (data[2] as u32) >> 4) as f64;
+        let adc_t = ((data[3] as u32) << 12 | (data[4] as u32) << 4 | (data[5] as u32) >> 4) as f64;
+        let adc_h = ((data[6] as u32) << 8 | data[7] as u32) as f64;
+
+        // Floating point formulas from the BME280 datasheet, section 8.1
+        let var1 = (adc_t / 16384.0 - self.t1 / 1024.0) * self.t2;
+        let var2 = (adc_t / 131072.0 - self.t1 / 8192.0).powi(2) * self.t3;
+        let t_fine = var1 + var2;
+        let temperature = t_fine / 5120.0;
+
+        let p = &self.p;
+        let mut var1 = t_fine / 2.0 - 64000.0;
+        let mut var2 = var1 * var1 * p[5] / 32768.0;
+        var2 += var1 * p[4] * 2.0;
+        var2 = var2 / 4.0 + p[3] * 65536.0;
+        var1 = (p[2] * var1 * var1 / 524288.0 + p[1] * var1) / 524288.0;
+        var1 = (1.0 + var1 / 32768.0) * p[0];
+        let pressure = if var1 == 0.0 {
+            0.0
+        } else {
+            let mut pa = 1048576.0 - adc_p;
+            pa = (pa - var2 / 4096.0) * 6250.0 / var1;
+            let var1 = p[8] * pa * pa / 2147483648.0;
+            let var2 = pa * p[7] / 32768.0;
+            pa + (var1 + var2 + p[6]) / 16.0
+        };
+
+        let h = t_fine - 76800.0;
+        let h = (adc_h - (self.h4 * 64.0 + self.h5 / 16384.0 * h))
+            * (self.h2 / 65536.0 * (1.0 + self.h6 / 67108864.0 * h * (1.0 + self.h3 / 67108864.0 * h)));
+        let humidity = (h * (1.0 - self.h1 * h / 524288.0)).clamp(0.0, 100.0);
+
+        (temperature, humidity, pressure / 100.0)
+    }
+}
+
+/// Bosch BME280 temperature, humidity and pressure sensor (forced mode)
+pub struct Bme280 {
+    address: u16,
+    calibration: Option<Calibration>,
+}
+
+impl Bme280 {
+    pub fn new(address: u16) -> Self {
+        Self {
+            address,
+            calibration: None,
+        }
+    }
+}
+
+#[async_trait]
+impl I2cChip for Bme280 {
+    fn init(&mut self, bus: &mut dyn I2cBus) -> anyhow::Result<()> {
+        let mut id = [0u8; 1];
+        bus.read_registers(self.address, REG_CHIP_ID, &mut id)?;
+        if id[0] != CHIP_ID {
+            anyhow::bail!("BME280 unexpected chip id 0x{:02x}", id[0]);
+        }
+
+        let mut tp = [0u8; 26];
+        let mut h = [0u8; 7];
+        bus.read_registers(self.address, REG_CALIB_TP, &mut tp)?;
+        bus.read_registers(self.address, REG_CALIB_H, &mut h)?;
+        self.calibration = Some(Calibration::parse(&tp, &h));
+        Ok(())
+    }
+
+    async fn measure(&mut self, bus: &mut dyn I2cBus) -> anyhow::Result<Vec<(u32, f64)>> {
+        let calibration = self
+            .calibration
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("BME280 is not initialized"))?;
+
+        // ctrl_hum takes effect after a write to ctrl_meas
+        bus.write(self.address, &[REG_CTRL_HUM, CTRL_HUM])?;
+        bus.write(self.address, &[REG_CTRL_MEAS, CTRL_MEAS_FORCED])?;
+        tokio::time::sleep(MEASURE_TIME).await;
+
+        let mut data = [0u8; 8];
+        bus.read_registers(self.address, REG_DATA, &mut data)?;
+        let (temperature, humidity, pressure) = calibration.compensate(&data);
+
+        Ok(vec![
+            (CHANNEL_TEMPERATURE, temperature),
+            (CHANNEL_HUMIDITY, humidity),
+            (CHANNEL_PRESSURE, pressure),
+        ])
+    }
+
+    fn channels(&self) -> Vec<(u32, String, &'static str)> {
+        vec![
+            (CHANNEL_TEMPERATURE, "temperature".to_string(), "°C"),
+            (CHANNEL_HUMIDITY, "humidity".to_string(), "%"),
+            (CHANNEL_PRESSURE, "pressure".to_string(), "hPa"),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fake::FakeBus;
+    use super::*;
+
+    const ADDRESS: u16 = 0x76;
+
+    // Trimming values of the datasheet example (T1..T3, P1..P9), H1..H6 of a real part
+    const CALIB_TP: [u8; 26] = [
+        0x70, 0x6b, 0x43, 0x67, 0x18, 0xfc, 0x7d, 0x8e, 0x43, 0xd6, 0xd0, 0x0b, 0x27, 0x0b, 0x8c, 0x00, 0xf9, 0xff,
+        0x8c, 0x3c, 0xf8, 0xc6, 0x70, 0x17, 0x00, 0x4b,
+    ];
+    const CALIB_H: [u8; 7] = [0x6a, 0x01, 0x00, 0x14, 0x24, 0x03, 0x1e];
+    // adc_P = 415148, adc_T = 519888, adc_H = 30000
+    const DATA: [u8; 8] = [0x65, 0x5a, 0xc0, 0x7e, 0xed, 0x00, 0x75, 0x30];
+
+    fn assert_near(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() < tolerance, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn calibration_parse() {
+        let c = Calibration::parse(&CALIB_TP, &CALIB_H);
+        assert_eq!((c.t1, c.t2, c.t3), (27504.0, 26435.0, -1000.0));
+        assert_eq!(
+            c.p,
+            [36477.0, -10685.0, 3024.0, 2855.0, 140.0, -7.0, 15500.0, -14600.0, 6000.0]
+        );
+        // H4 and H5 share the nibbles of 0xe5
+        assert_eq!((c.h1, c.h2, c.h3, c.h4, c.h5, c.h6), (75.0, 362.0, 0.0, 324.0, 50.0, 30.0));
+    }
+
+    #[test]
+    fn compensate_datasheet_example() {
+        let c = Calibration::parse(&CALIB_TP, &CALIB_H);
+        let (temperature, humidity, pressure) = c.compensate(&DATA);
+        assert_near(temperature, 25.08, 0.01);
+        assert_near(pressure, 1006.5327, 0.001);
+        assert_near(humidity, 51.083, 0.001);
+    }
+
+    #[tokio::test]
+    async fn measure_from_register_dump() {
+        let mut bus = FakeBus::default()
+            .registers(ADDRESS, REG_CALIB_TP, &CALIB_TP)
+            .registers(ADDRESS, REG_CALIB_H, &CALIB_H)
+            .registers(ADDRESS, REG_CHIP_ID, &[CHIP_ID])
+            .registers(ADDRESS, REG_DATA, &DATA);
+        let mut chip = Bme280::new(ADDRESS);
+        chip.init(&mut bus).unwrap();
+
+        let values = chip.measure(&mut bus).await.unwrap();
+        let channels: Vec<u32> = values.iter().map(|(ch, _)| *ch).collect();
+        assert_eq!(channels, vec![CHANNEL_TEMPERATURE, CHANNEL_HUMIDITY, CHANNEL_PRESSURE]);
+        assert_near(values[0].1, 25.08, 0.01);
+        assert!(bus.writes.contains(&(ADDRESS, vec![REG_CTRL_MEAS, CTRL_MEAS_FORCED])));
+    }
+
+    #[test]
+    fn wrong_chip_id_is_rejected() {
+        let mut bus = FakeBus::default().registers(ADDRESS, REG_CHIP_ID, &[0x58]);
+        assert!(Bme280::new(ADDRESS).init(&mut bus).is_err());
+    }
+}
@@ -0,0 +1,62 @@
+// ============================================================================
+// src/sensors/i2c/fake.rs
+// ============================================================================
+use super::I2cBus;
+use std::collections::{HashMap, VecDeque};
+
+/// In-memory bus for chip tests. Register chips have a 256 byte register
+/// file: the first written byte selects the register, the following ones
+/// and reads auto-increment from it. Command chips answer plain reads with
+/// queued responses.
+#[derive(Default)]
+pub struct FakeBus {
+    registers: HashMap<u16, [u8; 256]>,
+    pointers: HashMap<u16, u8>,
+    responses: HashMap<u16, VecDeque<Vec<u8>>>,
+    /// Every write, in order
+    pub writes: Vec<(u16, Vec<u8>)>,
+}
+
+impl FakeBus {
+    /// Load a register dump starting at `reg`
+    pub fn registers(mut self, addr: u16, reg: u8, dump: &[u8]) -> Self {
+        let file = self.registers.entry(addr).or_insert([0; 256]);
+        file[reg as usize..reg as usize + dump.len()].copy_from_slice(dump);
+        self
+    }
+
+    /// Queue the answer to the next plain read
+    pub fn response(mut self, addr: u16, data: &[u8]) -> Self {
+        self.responses.entry(addr).or_default().push_back(data.to_vec());
+        self
+    }
+}
+
+impl I2cBus for FakeBus {
+    fn write(&mut self, addr: u16, data: &[u8]) -> anyhow::Result<()> {
+        self.writes.push((addr, data.to_vec()));
+        if let Some(file) = self.registers.get_mut(&addr) {
+            if let Some((&reg, values)) = data.split_first() {
+                file[reg as usize..reg as usize + values.len()].copy_from_slice(values);
+                self.pointers.insert(addr, reg);
+            }
+        } else if !self.responses.contains_key(&addr) {
+            anyhow::bail!("no device at 0x{:02x}", addr);
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, addr: u16, buf: &mut [u8]) -> anyhow::Result<()> {
+        if let Some(data) = self.responses.get_mut(&addr).and_then(|queue| queue.pop_front()) {
+            buf.copy_from_slice(&data[..buf.len()]);
+            return Ok(());
+        }
+        let file = self
+            .registers
+            .get(&addr)
+            .ok_or_else(|| anyhow::anyhow!("no device at 0x{:02x}", addr))?;
+        let reg = self.pointers.get(&addr).copied().unwrap_or(0) as usize;
+        buf.copy_from_slice(&file[reg..reg + buf.len()]);
+        Ok(())
+    }
+}
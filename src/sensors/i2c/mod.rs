@@ -0,0 +1,245 @@
+// ============================================================================
+// src/sensors/i2c/mod.rs
+// ============================================================================
+use super::{ChannelInfo, SensorDriver, SensorValue};
+use crate::config::{I2cBusConfig, I2cConfig, I2cDevice};
+use async_trait::async_trait;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+pub mod ads1115;
+pub mod bme280;
+#[cfg(test)]
+pub mod fake;
+pub mod sht3x;
+
+/// Raw access to an I2C bus, implemented for /dev/i2c-N and by fakes
+/// replaying recorded register dumps
+pub trait I2cBus: Send {
+    fn write(&mut self, addr: u16, data: &[u8]) -> anyhow::Result<()>;
+
+    fn read(&mut self, addr: u16, buf: &mut [u8]) -> anyhow::Result<()>;
+
+    /// Select a register and read its content
+    fn read_registers(&mut self, addr: u16, reg: u8, buf: &mut [u8]) -> anyhow::Result<()> {
+        self.write(addr, &[reg])?;
+        self.read(addr, buf)
+    }
+}
+
+/// Chip specific setup and decoding
+#[async_trait]
+pub trait I2cChip: Send {
+    /// Check the chip and read calibration data
+    fn init(&mut self, bus: &mut dyn I2cBus) -> anyhow::Result<()>;
+
+    /// Run a measurement, returns (channel, value) pairs
+    async fn measure(&mut self, bus: &mut dyn I2cBus) -> anyhow::Result<Vec<(u32, f64)>>;
+
+    /// Provided channels as (channel, name, unit)
+    fn channels(&self) -> Vec<(u32, String, &'static str)>;
+}
+
+pub fn new_chip(device: &I2cDevice) -> anyhow::Result<Box<dyn I2cChip>> {
+    match device.chip.to_lowercase().as_str() {
+        "bme280" => Ok(Box::new(bme280::Bme280::new(device.address))),
+        "sht3x" | "sht31" | "sht30" => Ok(Box::new(sht3x::Sht3x::new(device.address))),
+        "ads1115" => Ok(Box::new(ads1115::Ads1115::new(
+            device.address,
+            device.gain,
+            device.inputs.clone(),
+        )?)),
+        other => anyhow::bail!("unknown I2C chip '{}'", other),
+    }
+}
+
+const I2C_SLAVE: libc::c_ulong = 0x0703;
+
+/// Linux i2c-dev bus
+pub struct LinuxI2cBus {
+    file: File,
+    slave: Option<u16>,
+}
+
+impl LinuxI2cBus {
+    pub fn open(bus: u32) -> anyhow::Result<Self> {
+        let path = format!("/dev/i2c-{}", bus);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path, e))?;
+        Ok(Self { file, slave: None })
+    }
+
+    fn select(&mut self, addr: u16) -> anyhow::Result<()> {
+        if self.slave != Some(addr) {
+            // SAFETY: plain ioctl on an owned, open file descriptor
+            let res = unsafe { libc::ioctl(self.file.as_raw_fd(), I2C_SLAVE as _, addr as libc::c_ulong) };
+            if res < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            self.slave = Some(addr);
+        }
+        Ok(())
+    }
+}
+
+impl I2cBus for LinuxI2cBus {
+    fn write(&mut self, addr: u16, data: &[u8]) -> anyhow::Result<()> {
+        self.select(addr)?;
+        self.file.write_all(data)?;
+        Ok(())
+    }
+
+    fn read(&mut self, addr: u16, buf: &mut [u8]) -> anyhow::Result<()> {
+        self.select(addr)?;
+        self.file.read_exact(buf)?;
+        Ok(())
+    }
+}
+
+struct ChipState {
+    device: I2cDevice,
+    chip: Box<dyn I2cChip>,
+    ready: bool,
+}
+
+/// All configured chips of one I2C bus
+pub struct I2cDriver {
+    bus_no: u32,
+    scan_rate: Duration,
+    bus: Option<Box<dyn I2cBus>>,
+    chips: Vec<ChipState>,
+}
+
+pub fn create(section: &serde_yaml::Value) -> anyhow::Result<Vec<Box<dyn SensorDriver>>> {
+    let config: I2cConfig = serde_yaml::from_value(section.clone())?;
+    let mut drivers: Vec<Box<dyn SensorDriver>> = Vec::new();
+    for bus in config.buses {
+        drivers.push(Box::new(I2cDriver::new(bus)));
+    }
+    Ok(drivers)
+}
+
+impl I2cDriver {
+    pub fn new(config: I2cBusConfig) -> Self {
+        let mut chips = Vec::new();
+        for device in config.devices {
+            // A bad entry is skipped, the other chips of the bus still run
+            match new_chip(&device) {
+                Ok(chip) => chips.push(ChipState { device, chip, ready: false }),
+                Err(e) => log::error!("I2C {}: {}", device.id, e),
+            }
+        }
+        Self {
+            bus_no: config.bus,
+            scan_rate: Duration::from_secs(config.scan_rate.max(1) as u64),
+            bus: None,
+            chips,
+        }
+    }
+}
+
+#[async_trait]
+impl SensorDriver for I2cDriver {
+    fn name(&self) -> String {
+        format!("i2c-{} ({} chips)", self.bus_no, self.chips.len())
+    }
+
+    async fn init(&mut self) -> anyhow::Result<()> {
+        if self.bus.is_none() {
+            self.bus = Some(Box::new(LinuxI2cBus::open(self.bus_no)?));
+        }
+        Ok(())
+    }
+
+    fn poll_interval(&self) -> Duration {
+        self.scan_rate
+    }
+
+    async fn poll(&mut self) -> anyhow::Result<Vec<SensorValue>> {
+        let bus = self
+            .bus
+            .as_deref_mut()
+            .ok_or_else(|| anyhow::anyhow!("i2c-{} is not open", self.bus_no))?;
+        let mut values = Vec::new();
+
+        for state in self.chips.iter_mut() {
+            // Chips failing at start (or unplugged later) are set up again
+            if !state.ready {
+                match state.chip.init(bus) {
+                    Ok(()) => {
+                        log::info!("I2C {} ({} at 0x{:02x}) ready", state.device.id, state.device.chip, state.device.address);
+                        state.ready = true;
+                    }
+                    Err(e) => {
+                        log::error!("I2C {} init error: {}", state.device.id, e);
+                        continue;
+                    }
+                }
+            }
+
+            match state.chip.measure(bus).await {
+                Ok(measured) => values.extend(
+                    measured
+                        .into_iter()
+                        .map(|(channel, value)| SensorValue::now(&state.device.id, channel, value)),
+                ),
+                Err(e) => {
+                    log::error!("I2C {} read error: {}", state.device.id, e);
+                    state.ready = false;
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    fn channels(&self) -> Vec<ChannelInfo> {
+        self.chips
+            .iter()
+            .flat_map(|state| {
+                state.chip.channels().into_iter().map(|(channel, name, unit)| ChannelInfo {
+                    device: state.device.id.clone(),
+                    channel,
+                    name: match &state.device.name {
+                        Some(device_name) => format!("{} {}", device_name, name),
+                        None => name,
+                    },
+                    unit: Some(unit.to_string()),
+                    writable: false,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_devices_are_skipped() {
+        let section: serde_yaml::Value = serde_yaml::from_str(
+            r#"
+buses:
+- bus: 1
+  scan_rate: 10
+  devices:
+  - {id: "th", chip: "sht3x", address: 0x44}
+  - {id: "x", chip: "unknown", address: 0x10}
+  - {id: "adc", chip: "ads1115", address: 0x48, gain: 3.3}
+- bus: 2
+  scan_rate: 10
+  devices:
+  - {id: "env", chip: "bme280", address: 0x76}
+"#,
+        )
+        .unwrap();
+        let drivers = create(&section).unwrap();
+        let names: Vec<_> = drivers.iter().map(|d| d.name()).collect();
+        assert_eq!(names, ["i2c-1 (1 chips)", "i2c-2 (1 chips)"]);
+    }
+}
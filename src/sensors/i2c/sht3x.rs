@@ -0,0 +1,116 @@
+// ============================================================================
+// src/sensors/i2c/sht3x.rs
+// ============================================================================
+use super::{I2cBus, I2cChip};
+use async_trait::async_trait;
+use std::time::Duration;
+
+// Single shot, high repeatability, no clock stretching
+const CMD_MEASURE: [u8; 2] = [0x24, 0x00];
+const CMD_SOFT_RESET: [u8; 2] = [0x30, 0xa2];
+const MEASURE_TIME: Duration = Duration::from_millis(16);
+
+pub const CHANNEL_TEMPERATURE: u32 = 0;
+pub const CHANNEL_HUMIDITY: u32 = 1;
+
+/// Sensirion SHT30/31/35 temperature and humidity sensor
+pub struct Sht3x {
+    address: u16,
+}
+
+impl Sht3x {
+    pub fn new(address: u16) -> Self {
+        Self { address }
+    }
+}
+
+#[async_trait]
+impl I2cChip for Sht3x {
+    fn init(&mut self, bus: &mut dyn I2cBus) -> anyhow::Result<()> {
+        bus.write(self.address, &CMD_SOFT_RESET)
+    }
+
+    async fn measure(&mut self, bus: &mut dyn I2cBus) -> anyhow::Result<Vec<(u32, f64)>> {
+        bus.write(self.address, &CMD_MEASURE)?;
+        tokio::time::sleep(MEASURE_TIME).await;
+
+        let mut buf = [0u8; 6];
+        bus.read(self.address, &mut buf)?;
+        let (temperature, humidity) = decode(&buf)?;
+        Ok(vec![(CHANNEL_TEMPERATURE, temperature), (CHANNEL_HUMIDITY, humidity)])
+    }
+
+    fn channels(&self) -> Vec<(u32, String, &'static str)> {
+        vec![
+            (CHANNEL_TEMPERATURE, "temperature".to_string(), "°C"),
+            (CHANNEL_HUMIDITY, "humidity".to_string(), "%"),
+        ]
+    }
+}
+
+/// Decode a measurement: temperature and humidity words, each followed by CRC
+pub fn decode(buf: &[u8; 6]) -> anyhow::Result<(f64, f64)> {
+    for word in buf.chunks(3) {
+        if crc8(&word[..2]) != word[2] {
+            anyhow::bail!("SHT3x CRC error in {:02x?}", buf);
+        }
+    }
+    let raw_t = u16::from_be_bytes([buf[0], buf[1]]) as f64;
+    let raw_rh = u16::from_be_bytes([buf[3], buf[4]]) as f64;
+    Ok((-45.0 + 175.0 * raw_t / 65535.0, 100.0 * raw_rh / 65535.0))
+}
+
+/// CRC-8, polynomial 0x31, init 0xff
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc: u8 = 0xff;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x31 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fake::FakeBus;
+    use super::*;
+
+    const ADDRESS: u16 = 0x44;
+    // T = 0x664e (24.94 °C), RH = 0x8f5c (56.00 %), each word followed by its CRC
+    const MEASUREMENT: [u8; 6] = [0x66, 0x4e, 0xac, 0x8f, 0x5c, 0x38];
+
+    #[test]
+    fn crc8_datasheet_example() {
+        assert_eq!(crc8(&[0xbe, 0xef]), 0x92);
+    }
+
+    #[test]
+    fn decode_measurement() {
+        let (temperature, humidity) = decode(&MEASUREMENT).unwrap();
+        assert!((temperature - 24.9359).abs() < 1e-3, "{}", temperature);
+        assert!((humidity - 56.0006).abs() < 1e-3, "{}", humidity);
+    }
+
+    #[test]
+    fn decode_rejects_bad_crc() {
+        let mut buf = MEASUREMENT;
+        buf[5] ^= 0x01;
+        assert!(decode(&buf).is_err());
+    }
+
+    #[tokio::test]
+    async fn measure_sends_single_shot_command() {
+        let mut bus = FakeBus::default().response(ADDRESS, &MEASUREMENT);
+        let mut chip = Sht3x::new(ADDRESS);
+        chip.init(&mut bus).unwrap();
+
+        let values = chip.measure(&mut bus).await.unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(
+            bus.writes,
+            vec![(ADDRESS, CMD_SOFT_RESET.to_vec()), (ADDRESS, CMD_MEASURE.to_vec())]
+        );
+    }
+}
@@ -0,0 +1,223 @@
+// ============================================================================
+// src/sensors/mod.rs
+// ============================================================================
+use crate::config::SensorsConfig;
+use crate::mqtt_client::SsnMqttClient;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+pub mod ds18b20;
+pub mod exec;
+pub mod gpio;
+pub mod i2c;
+pub mod sysfs;
+pub mod watchdog_tcp;
+
+/// One value read from a local sensor
+#[derive(Debug, Clone)]
+pub struct SensorValue {
+    pub device: String,
+    pub channel: u32,
+    pub value: f64,
+    pub timestamp: i64,
+}
+
+impl SensorValue {
+    pub fn now(device: &str, channel: u32, value: f64) -> Self {
+        Self {
+            device: device.to_string(),
+            channel,
+            value,
+            timestamp: chrono::Utc::now().timestamp(),
+        }
+    }
+}
+
+/// Description of a channel provided by a driver
+#[derive(Debug, Clone)]
+pub struct ChannelInfo {
+    pub device: String,
+    pub channel: u32,
+    pub name: String,
+    pub unit: Option<String>,
+    pub writable: bool,
+}
+
+#[async_trait]
+pub trait SensorDriver: Send {
+    /// Human readable driver instance name (for logs)
+    fn name(&self) -> String;
+
+    /// Prepare the hardware, called once before the first poll
+    async fn init(&mut self) -> anyhow::Result<()>;
+
+    /// Period between poll() calls
+    fn poll_interval(&self) -> Duration;
+
+    /// Read the sensors, returns the values to be published
+    async fn poll(&mut self) -> anyhow::Result<Vec<SensorValue>>;
+
+    /// Called for each value of poll() once it was published successfully
+    fn published(&mut self, _value: &SensorValue) {}
+
+    /// Set an output channel
+    async fn write(&mut self, device: &str, channel: u32, _value: f64) -> anyhow::Result<()> {
+        anyhow::bail!("{} channel {} is read only", device, channel)
+    }
+
+    /// Channels provided by this driver
+    fn channels(&self) -> Vec<ChannelInfo>;
+}
+
+/// Builds driver instances from a `sensors:` config section
+pub type DriverFactory = fn(&serde_yaml::Value) -> anyhow::Result<Vec<Box<dyn SensorDriver>>>;
+
+/// Known drivers keyed by their `sensors:` section name
+pub fn registry() -> HashMap<&'static str, DriverFactory> {
+    let mut drivers: HashMap<&'static str, DriverFactory> = HashMap::new();
+    drivers.insert("gpio", gpio::create);
+    drivers.insert("ds18b20", ds18b20::create);
+    drivers.insert("watchdog_tcp", watchdog_tcp::create);
+    drivers.insert("sysfs", sysfs::create);
+    drivers.insert("exec", exec::create);
+    drivers.insert("i2c", i2c::create);
+    drivers
+}
+
+struct WriteRequest {
+    device: String,
+    channel: u32,
+    value: f64,
+    reply: oneshot::Sender<anyhow::Result<()>>,
+}
+
+/// Running sensor drivers, each one polled in its own task
+pub struct SensorManager {
+    obj: u32,
+    channels: Vec<ChannelInfo>,
+    writers: HashMap<String, mpsc::Sender<WriteRequest>>,
+}
+
+impl SensorManager {
+    /// Create drivers for all configured sections and spawn them
+    pub fn start(config: &SensorsConfig, mqtt_client: Arc<SsnMqttClient>) -> Self {
+        let registry = registry();
+        let mut manager = Self {
+            obj: config.obj,
+            channels: Vec::new(),
+            writers: HashMap::new(),
+        };
+
+        for (section, value) in &config.sections {
+            let Some(factory) = registry.get(section.as_str()) else {
+                log::warn!("No sensor driver for section '{}'", section);
+                continue;
+            };
+
+            let drivers = match factory(value) {
+                Ok(drivers) => drivers,
+                Err(e) => {
+                    log::error!("Sensors section '{}' config error: {}", section, e);
+                    continue;
+                }
+            };
+
+            for driver in drivers {
+                let channels = driver.channels();
+                let (tx, rx) = mpsc::channel(16);
+                for info in &channels {
+                    if info.writable {
+                        manager.writers.insert(info.device.clone(), tx.clone());
+                    }
+                }
+                manager.channels.extend(channels);
+
+                log::info!("Starting sensor driver {}", driver.name());
+                tokio::spawn(run_driver(driver, config.obj, mqtt_client.clone(), rx));
+            }
+        }
+
+        manager
+    }
+
+    pub fn obj(&self) -> u32 {
+        self.obj
+    }
+
+    pub fn channels(&self) -> &[ChannelInfo] {
+        &self.channels
+    }
+
+    /// Send a value to a writable channel of a local device
+    pub async fn write(&self, device: &str, channel: u32, value: f64) -> anyhow::Result<()> {
+        let writer = self
+            .writers
+            .get(device)
+            .ok_or_else(|| anyhow::anyhow!("unknown or read only device {}", device))?;
+
+        let (reply, result) = oneshot::channel();
+        writer
+            .send(WriteRequest {
+                device: device.to_string(),
+                channel,
+                value,
+                reply,
+            })
+            .await
+            .map_err(|_| anyhow::anyhow!("sensor driver for {} stopped", device))?;
+        result.await?
+    }
+}
+
+async fn run_driver(
+    mut driver: Box<dyn SensorDriver>,
+    obj: u32,
+    mqtt_client: Arc<SsnMqttClient>,
+    mut writes: mpsc::Receiver<WriteRequest>,
+) {
+    if let Err(e) = driver.init().await {
+        log::error!("Sensor driver {} init error: {}", driver.name(), e);
+        return;
+    }
+
+    let units: HashMap<(String, u32), String> = driver
+        .channels()
+        .into_iter()
+        .filter_map(|ch| Some(((ch.device, ch.channel), ch.unit?)))
+        .collect();
+
+    let mut poll = tokio::time::interval(driver.poll_interval());
+    poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            _ = poll.tick() => {
+                let values = match driver.poll().await {
+                    Ok(values) => values,
+                    Err(e) => {
+                        log::error!("Sensor driver {} poll error: {}", driver.name(), e);
+                        continue;
+                    }
+                };
+                for v in values {
+                    let unit = units.get(&(v.device.clone(), v.channel)).map(String::as_str);
+                    if let Err(e) = mqtt_client
+                        .publish_sensor_value(obj, &v.device, v.channel, v.value, v.timestamp, 0, unit)
+                        .await
+                    {
+                        log::error!("Sensor {} publish error: {}", v.device, e);
+                        continue;
+                    }
+                    driver.published(&v);
+                }
+            }
+            Some(req) = writes.recv() => {
+                let result = driver.write(&req.device, req.channel, req.value).await;
+                let _ = req.reply.send(result);
+            }
+        }
+    }
+}
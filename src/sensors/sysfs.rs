@@ -0,0 +1,380 @@
+// ============================================================================
+// src/sensors/sysfs.rs
+// ============================================================================
+use super::{ChannelInfo, SensorDriver, SensorValue};
+use crate::config::{SysfsChannel, SysfsConfig};
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const DEFAULT_ROOT: &str = "/sys";
+const HWMON_CLASS: &str = "class/hwmon";
+const IIO_DEVICES: &str = "bus/iio/devices";
+
+/// Linux hwmon and IIO attributes (CPU temperature, voltages, ADCs)
+pub struct SysfsDriver {
+    root: PathBuf,
+    scan_rate: Duration,
+    channels: Vec<SysfsChannel>,
+    resolved: Vec<Option<Attribute>>,
+}
+
+/// Attribute file with the scaling resolved at init
+#[derive(Debug)]
+struct Attribute {
+    path: PathBuf,
+    scale: f64,
+    offset: f64,
+}
+
+pub fn create(section: &serde_yaml::Value) -> anyhow::Result<Vec<Box<dyn SensorDriver>>> {
+    let config: SysfsConfig = serde_yaml::from_value(section.clone())?;
+    Ok(vec![Box::new(SysfsDriver::new(config))])
+}
+
+impl SysfsDriver {
+    pub fn new(config: SysfsConfig) -> Self {
+        Self {
+            root: PathBuf::from(config.root.as_deref().unwrap_or(DEFAULT_ROOT)),
+            scan_rate: Duration::from_secs(config.scan_rate.max(1) as u64),
+            channels: config.channels,
+            resolved: Vec::new(),
+        }
+    }
+
+    fn resolve(&self, ch: &SysfsChannel) -> anyhow::Result<Attribute> {
+        if let Some(name) = &ch.hwmon {
+            let dir = find_by_name(&self.root.join(HWMON_CLASS), name)?;
+            let (scale, offset) = hwmon_default_scale(&ch.attribute);
+            Ok(Attribute {
+                path: dir.join(&ch.attribute),
+                scale: ch.scale.unwrap_or(scale),
+                offset: ch.offset.unwrap_or(offset),
+            })
+        } else if let Some(name) = &ch.iio {
+            let dir = find_by_name(&self.root.join(IIO_DEVICES), name)?;
+            // Only `_raw` values need the chip scale and offset, `_input`
+            // ones are processed already. Both are in milli units.
+            let milli = iio_type(&ch.attribute).map_or(1.0, |(factor, _)| factor);
+            let raw = ch.attribute.ends_with("_raw");
+            Ok(Attribute {
+                path: dir.join(&ch.attribute),
+                scale: match ch.scale {
+                    Some(scale) => scale,
+                    None if raw => iio_attribute(&dir, &ch.attribute, "scale").unwrap_or(1.0) * milli,
+                    None => milli,
+                },
+                offset: match ch.offset {
+                    Some(offset) => offset,
+                    None if raw => iio_attribute(&dir, &ch.attribute, "offset").unwrap_or(0.0),
+                    None => 0.0,
+                },
+            })
+        } else {
+            Ok(Attribute {
+                path: self.root.join(ch.attribute.trim_start_matches('/')),
+                scale: ch.scale.unwrap_or(1.0),
+                offset: ch.offset.unwrap_or(0.0),
+            })
+        }
+    }
+}
+
+#[async_trait]
+impl SensorDriver for SysfsDriver {
+    fn name(&self) -> String {
+        format!("sysfs ({} channels at {})", self.channels.len(), self.root.display())
+    }
+
+    async fn init(&mut self) -> anyhow::Result<()> {
+        self.resolved = self
+            .channels
+            .iter()
+            .map(|ch| match self.resolve(ch) {
+                Ok(attr) => {
+                    log::info!("sysfs {}/{}: {:?}", ch.device, ch.channel, attr);
+                    Some(attr)
+                }
+                Err(e) => {
+                    log::error!("sysfs {}/{} not found: {}", ch.device, ch.channel, e);
+                    None
+                }
+            })
+            .collect();
+        Ok(())
+    }
+
+    fn poll_interval(&self) -> Duration {
+        self.scan_rate
+    }
+
+    async fn poll(&mut self) -> anyhow::Result<Vec<SensorValue>> {
+        let mut values = Vec::new();
+        for (ch, attr) in self.channels.iter().zip(&self.resolved) {
+            let Some(attr) = attr else {
+                continue;
+            };
+            match read_number(&attr.path) {
+                Ok(raw) => values.push(SensorValue::now(
+                    &ch.device,
+                    ch.channel,
+                    (raw + attr.offset) * attr.scale,
+                )),
+                Err(e) => log::error!("sysfs {} read error: {}", attr.path.display(), e),
+            }
+        }
+        Ok(values)
+    }
+
+    fn channels(&self) -> Vec<ChannelInfo> {
+        self.channels
+            .iter()
+            .map(|ch| ChannelInfo {
+                device: ch.device.clone(),
+                channel: ch.channel,
+                name: ch.name.clone().unwrap_or_else(|| ch.attribute.clone()),
+                unit: ch.unit.clone().or_else(|| {
+                    if ch.hwmon.is_some() {
+                        hwmon_unit(&ch.attribute)
+                    } else if ch.iio.is_some() {
+                        iio_type(&ch.attribute).map(|(_, unit)| unit.to_string())
+                    } else {
+                        None
+                    }
+                }),
+                writable: false,
+            })
+            .collect()
+    }
+}
+
+/// Find the chip directory under `class_dir` whose `name` file matches
+fn find_by_name(class_dir: &Path, name: &str) -> anyhow::Result<PathBuf> {
+    for entry in std::fs::read_dir(class_dir)? {
+        let dir = entry?.path();
+        if let Ok(chip) = std::fs::read_to_string(dir.join("name")) {
+            if chip.trim() == name {
+                return Ok(dir);
+            }
+        }
+    }
+    anyhow::bail!("no chip named '{}' in {}", name, class_dir.display())
+}
+
+/// hwmon attributes are `<type><index>_<item>`, e.g. `in0_input`
+/// (but not `intrusion0_alarm`)
+fn hwmon_type(attribute: &str, kind: &str) -> bool {
+    attribute
+        .strip_prefix(kind)
+        .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+/// hwmon reports millidegrees, millivolts, milliamps and microwatts
+fn hwmon_default_scale(attribute: &str) -> (f64, f64) {
+    if ["temp", "in", "curr"].iter().any(|kind| hwmon_type(attribute, kind)) {
+        (0.001, 0.0)
+    } else if hwmon_type(attribute, "power") {
+        (0.000001, 0.0)
+    } else {
+        (1.0, 0.0)
+    }
+}
+
+fn hwmon_unit(attribute: &str) -> Option<String> {
+    let unit = if hwmon_type(attribute, "temp") {
+        "°C"
+    } else if hwmon_type(attribute, "in") {
+        "V"
+    } else if hwmon_type(attribute, "curr") {
+        "A"
+    } else if hwmon_type(attribute, "power") {
+        "W"
+    } else if hwmon_type(attribute, "fan") {
+        "RPM"
+    } else {
+        return None;
+    };
+    Some(unit.to_string())
+}
+
+/// IIO channel types reported in milli units (mV, m°C, mA, m%RH):
+/// factor to the base unit and the unit
+fn iio_type(attribute: &str) -> Option<(f64, &'static str)> {
+    let kind = attribute.strip_prefix("in_")?;
+    [
+        ("voltage", "V"),
+        ("temp", "°C"),
+        ("current", "A"),
+        ("humidityrelative", "%"),
+    ]
+    .iter()
+    .find(|(name, _)| kind.starts_with(name))
+    .map(|(_, unit)| (0.001, *unit))
+}
+
+/// Read the IIO `<channel>_<kind>` attribute (e.g. in_voltage0_scale),
+/// falling back to the shared one of the channel type (in_voltage_scale)
+fn iio_attribute(dir: &Path, attribute: &str, kind: &str) -> Option<f64> {
+    let prefix = attribute.strip_suffix("_raw").unwrap_or(attribute);
+    let shared = prefix.trim_end_matches(|c: char| c.is_ascii_digit());
+
+    [prefix, shared]
+        .iter()
+        .find_map(|p| read_number(&dir.join(format!("{}_{}", p, kind))).ok())
+}
+
+fn read_number(path: &Path) -> anyhow::Result<f64> {
+    let content = std::fs::read_to_string(path)?;
+    Ok(content.trim().parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Temporary sysfs tree, removed on drop
+    struct FakeSysfs {
+        root: PathBuf,
+    }
+
+    impl FakeSysfs {
+        fn new(test: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("ssn-sysfs-{}-{}", std::process::id(), test));
+            let _ = std::fs::remove_dir_all(&root);
+            Self { root }
+        }
+
+        fn file(&self, path: &str, content: &str) -> &Self {
+            let path = self.root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+            self
+        }
+
+        fn driver(&self, channels: Vec<SysfsChannel>) -> SysfsDriver {
+            SysfsDriver::new(SysfsConfig {
+                scan_rate: 1,
+                root: Some(self.root.to_string_lossy().into_owned()),
+                channels,
+            })
+        }
+    }
+
+    impl Drop for FakeSysfs {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    fn channel(channel: u32, hwmon: Option<&str>, iio: Option<&str>, attribute: &str) -> SysfsChannel {
+        SysfsChannel {
+            device: "dev".to_string(),
+            channel,
+            name: None,
+            hwmon: hwmon.map(str::to_string),
+            iio: iio.map(str::to_string),
+            attribute: attribute.to_string(),
+            scale: None,
+            offset: None,
+            unit: None,
+        }
+    }
+
+    async fn poll(driver: &mut SysfsDriver) -> Vec<(u32, f64)> {
+        driver.init().await.unwrap();
+        let values = driver.poll().await.unwrap();
+        values.iter().map(|v| (v.channel, v.value)).collect()
+    }
+
+    fn assert_values(actual: &[(u32, f64)], expected: &[(u32, f64)]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for ((ch, v), (ech, ev)) in actual.iter().zip(expected) {
+            assert_eq!(ch, ech);
+            assert!((v - ev).abs() < 1e-9, "channel {}: {} != {}", ch, v, ev);
+        }
+    }
+
+    #[tokio::test]
+    async fn hwmon_attributes_are_scaled_by_type() {
+        let fs = FakeSysfs::new("hwmon");
+        fs.file("class/hwmon/hwmon0/name", "other\n")
+            .file("class/hwmon/hwmon1/name", "cpu_thermal\n")
+            .file("class/hwmon/hwmon1/temp1_input", "45500\n")
+            .file("class/hwmon/hwmon1/in0_input", "1200\n")
+            .file("class/hwmon/hwmon1/power1_input", "2500000\n")
+            .file("class/hwmon/hwmon1/intrusion0_alarm", "1\n");
+        let mut driver = fs.driver(vec![
+            channel(0, Some("cpu_thermal"), None, "temp1_input"),
+            channel(1, Some("cpu_thermal"), None, "in0_input"),
+            channel(2, Some("cpu_thermal"), None, "power1_input"),
+            channel(3, Some("cpu_thermal"), None, "intrusion0_alarm"),
+        ]);
+
+        assert_values(&poll(&mut driver).await, &[(0, 45.5), (1, 1.2), (2, 2.5), (3, 1.0)]);
+        let units: Vec<_> = driver.channels().into_iter().map(|c| c.unit).collect();
+        assert_eq!(
+            units,
+            vec![Some("°C".to_string()), Some("V".to_string()), Some("W".to_string()), None]
+        );
+    }
+
+    #[tokio::test]
+    async fn iio_raw_uses_chip_scale_and_offset() {
+        let fs = FakeSysfs::new("iio-raw");
+        fs.file("bus/iio/devices/iio:device0/name", "adc\n")
+            .file("bus/iio/devices/iio:device0/in_voltage0_raw", "1000\n")
+            .file("bus/iio/devices/iio:device0/in_voltage1_raw", "1000\n")
+            .file("bus/iio/devices/iio:device0/in_voltage_scale", "0.5\n")
+            .file("bus/iio/devices/iio:device0/in_voltage1_scale", "2\n")
+            .file("bus/iio/devices/iio:device0/in_voltage1_offset", "-500\n");
+        let mut driver = fs.driver(vec![
+            channel(0, None, Some("adc"), "in_voltage0_raw"),
+            channel(1, None, Some("adc"), "in_voltage1_raw"),
+        ]);
+
+        // Shared scale: 1000 * 0.5 mV, own scale and offset: (1000 - 500) * 2 mV
+        assert_values(&poll(&mut driver).await, &[(0, 0.5), (1, 1.0)]);
+        assert_eq!(driver.channels()[0].unit.as_deref(), Some("V"));
+    }
+
+    #[tokio::test]
+    async fn iio_input_is_not_scaled_again() {
+        let fs = FakeSysfs::new("iio-input");
+        fs.file("bus/iio/devices/iio:device0/name", "bme280\n")
+            .file("bus/iio/devices/iio:device0/in_temp_input", "21500\n")
+            .file("bus/iio/devices/iio:device0/in_temp_scale", "10\n")
+            .file("bus/iio/devices/iio:device0/in_pressure_input", "101.325\n");
+        let mut driver = fs.driver(vec![
+            channel(0, None, Some("bme280"), "in_temp_input"),
+            channel(1, None, Some("bme280"), "in_pressure_input"),
+        ]);
+
+        assert_values(&poll(&mut driver).await, &[(0, 21.5), (1, 101.325)]);
+    }
+
+    #[tokio::test]
+    async fn config_scale_and_plain_paths() {
+        let fs = FakeSysfs::new("plain");
+        fs.file("class/thermal/thermal_zone0/temp", "38000\n");
+        let mut ch = channel(0, None, None, "/class/thermal/thermal_zone0/temp");
+        ch.scale = Some(0.001);
+        ch.offset = Some(-1000.0);
+        let mut driver = fs.driver(vec![ch]);
+
+        assert_values(&poll(&mut driver).await, &[(0, 37.0)]);
+    }
+
+    #[tokio::test]
+    async fn missing_chips_and_files_are_skipped() {
+        let fs = FakeSysfs::new("missing");
+        fs.file("class/hwmon/hwmon0/name", "cpu_thermal\n")
+            .file("class/hwmon/hwmon0/temp1_input", "40000\n");
+        let mut driver = fs.driver(vec![
+            channel(0, Some("gpu_thermal"), None, "temp1_input"),
+            channel(1, Some("cpu_thermal"), None, "temp2_input"),
+            channel(2, Some("cpu_thermal"), None, "temp1_input"),
+        ]);
+
+        assert_values(&poll(&mut driver).await, &[(2, 40.0)]);
+    }
+}
@@ -0,0 +1,78 @@
+// ============================================================================
+// src/sensors/watchdog_tcp.rs
+// ============================================================================
+use super::{ChannelInfo, SensorDriver, SensorValue};
+use crate::config::{WatchdogDestination, WatchdogTcpConfig};
+use async_trait::async_trait;
+use std::time::Duration;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Publishes 1 when the destination is reachable, 0 otherwise.
+/// `command: "ping"` sends ICMP echo, `command: "connect"` opens a TCP
+/// connection to `address` given as host:port.
+pub struct WatchdogTcpDriver {
+    destination: WatchdogDestination,
+}
+
+pub fn create(section: &serde_yaml::Value) -> anyhow::Result<Vec<Box<dyn SensorDriver>>> {
+    let config: WatchdogTcpConfig = serde_yaml::from_value(section.clone())?;
+    Ok(config
+        .destinations
+        .into_iter()
+        .map(|destination| Box::new(WatchdogTcpDriver { destination }) as Box<dyn SensorDriver>)
+        .collect())
+}
+
+impl WatchdogTcpDriver {
+    async fn check(&self) -> anyhow::Result<bool> {
+        match self.destination.command.as_str() {
+            "ping" => {
+                let status = tokio::process::Command::new("ping")
+                    .args(["-c", "1", "-W", &CHECK_TIMEOUT.as_secs().to_string()])
+                    .arg(&self.destination.address)
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null())
+                    .status()
+                    .await?;
+                Ok(status.success())
+            }
+            "connect" => {
+                let connect = tokio::net::TcpStream::connect(&self.destination.address);
+                Ok(matches!(tokio::time::timeout(CHECK_TIMEOUT, connect).await, Ok(Ok(_))))
+            }
+            other => anyhow::bail!("unknown watchdog command '{}'", other),
+        }
+    }
+}
+
+#[async_trait]
+impl SensorDriver for WatchdogTcpDriver {
+    fn name(&self) -> String {
+        format!("watchdog_tcp {} ({})", self.destination.id, self.destination.address)
+    }
+
+    async fn init(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.destination.scan_rate.max(1) as u64)
+    }
+
+    async fn poll(&mut self) -> anyhow::Result<Vec<SensorValue>> {
+        let alive = self.check().await?;
+        log::debug!("Watchdog {} alive: {}", self.destination.id, alive);
+        Ok(vec![SensorValue::now(&self.destination.id, 0, alive as u8 as f64)])
+    }
+
+    fn channels(&self) -> Vec<ChannelInfo> {
+        vec![ChannelInfo {
+            device: self.destination.id.clone(),
+            channel: 0,
+            name: format!("{} state", self.destination.address),
+            unit: None,
+            writable: false,
+        }]
+    }
+}
@@ -0,0 +1,378 @@
+// ============================================================================
+// src/spool.rs
+// ============================================================================
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+
+/// Publish waiting for the broker
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SpooledMessage {
+    pub topic: String,
+    pub payload: String,
+    pub qos: u8,
+    pub retain: bool,
+    pub ts: i64,
+}
+
+/// Entry of a spool, the timestamp is used to expire old entries
+pub trait SpoolItem: Serialize + DeserializeOwned {
+    fn timestamp(&self) -> i64;
+}
+
+impl SpoolItem for SpooledMessage {
+    fn timestamp(&self) -> i64 {
+        self.ts
+    }
+}
+
+/// Bounded FIFO of pending publishes (or other items), mirrored to an
+/// append-only JSON lines file (when configured) so it survives restarts
+pub struct Spool<T: SpoolItem = SpooledMessage> {
+    path: Option<String>,
+    file: Option<File>,
+    queue: VecDeque<T>,
+    max_len: usize,
+    max_age: Option<i64>,
+    dropped: u64,
+    /// Lines in the file which are no longer in the queue
+    stale: usize,
+    /// Entries removed from the front so far, the id of the current front
+    head: u64,
+}
+
+impl<T: SpoolItem> Spool<T> {
+    pub fn open(path: Option<&str>, max_len: usize, max_age: Option<i64>) -> anyhow::Result<Self> {
+        let mut spool = Self {
+            path: path.map(str::to_string),
+            file: None,
+            queue: VecDeque::new(),
+            max_len: max_len.max(1),
+            max_age,
+            dropped: 0,
+            stale: 0,
+            head: 0,
+        };
+
+        if let Some(path) = path {
+            if let Ok(file) = File::open(path) {
+                for line in BufReader::new(file).lines() {
+                    match serde_json::from_str::<T>(&line?) {
+                        Ok(msg) => spool.queue.push_back(msg),
+                        Err(e) => log::warn!("Spool {}: skip corrupted line: {}", path, e),
+                    }
+                }
+            }
+            spool.expire();
+            while spool.queue.len() > spool.max_len {
+                spool.queue.pop_front();
+                spool.dropped += 1;
+            }
+            spool.rewrite()?;
+            if !spool.queue.is_empty() {
+                log::info!("Spool {}: {} entries restored", path, spool.queue.len());
+            }
+        }
+
+        Ok(spool)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Messages lost because the spool was full or they were too old
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn push(&mut self, msg: T) -> anyhow::Result<()> {
+        if self.queue.len() >= self.max_len {
+            self.pop_front_raw();
+            self.dropped += 1;
+            if self.dropped.is_power_of_two() {
+                log::warn!("Spool full ({} messages), {} dropped so far", self.max_len, self.dropped);
+            }
+        }
+
+        if let Some(file) = self.file.as_mut() {
+            writeln!(file, "{}", serde_json::to_string(&msg)?)?;
+        }
+        self.queue.push_back(msg);
+
+        if self.stale > self.max_len {
+            self.rewrite()?;
+        }
+        Ok(())
+    }
+
+    /// Oldest message which is not expired
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.expire();
+        self.pop_front_raw()
+    }
+
+    /// Put back a message which could not be sent
+    pub fn push_front(&mut self, msg: T) {
+        self.queue.push_front(msg);
+        self.head = self.head.saturating_sub(1);
+        self.stale = self.stale.saturating_sub(1);
+    }
+
+    /// Oldest message which is not expired with its id, it stays in the
+    /// spool until removed with [`Spool::remove`]
+    pub fn front(&mut self) -> Option<(u64, &T)> {
+        self.expire();
+        let head = self.head;
+        self.queue.front().map(|msg| (head, msg))
+    }
+
+    /// Remove the message returned by [`Spool::front`] once it was sent,
+    /// nothing is removed if it was dropped meanwhile. The file is emptied
+    /// when the last message is gone.
+    pub fn remove(&mut self, id: u64) -> anyhow::Result<()> {
+        if id != self.head || self.pop_front_raw().is_none() {
+            return Ok(());
+        }
+        if self.queue.is_empty() {
+            if let Some(file) = self.file.as_mut() {
+                file.set_len(0)?;
+            }
+            self.stale = 0;
+        }
+        Ok(())
+    }
+
+    fn pop_front_raw(&mut self) -> Option<T> {
+        let msg = self.queue.pop_front()?;
+        self.head += 1;
+        self.stale += 1;
+        Some(msg)
+    }
+
+    /// Drop the sent messages from the file
+    pub fn compact(&mut self) -> anyhow::Result<()> {
+        if self.stale > 0 {
+            self.rewrite()?;
+        }
+        Ok(())
+    }
+
+    fn expire(&mut self) {
+        let Some(max_age) = self.max_age else {
+            return;
+        };
+        let oldest = chrono::Utc::now().timestamp() - max_age;
+        while self.queue.front().is_some_and(|m| m.timestamp() < oldest) {
+            self.pop_front_raw();
+            self.dropped += 1;
+        }
+    }
+
+    fn rewrite(&mut self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            self.stale = 0;
+            return Ok(());
+        };
+
+        // Write to a temporary file first so a crash never leaves a truncated spool
+        let tmp = format!("{}.tmp", path);
+        let mut file = File::create(&tmp)?;
+        for msg in &self.queue {
+            writeln!(file, "{}", serde_json::to_string(msg)?)?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)?;
+
+        self.file = Some(OpenOptions::new().append(true).open(path)?);
+        self.stale = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Temporary spool file, removed on drop
+    struct TempFile(String);
+
+    impl TempFile {
+        fn new(test: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("ssn-spool-{}-{}.jsonl", std::process::id(), test));
+            let _ = std::fs::remove_file(&path);
+            Self(path.to_string_lossy().into_owned())
+        }
+
+        fn lines(&self) -> Vec<String> {
+            std::fs::read_to_string(&self.0)
+                .unwrap_or_default()
+                .lines()
+                .map(str::to_string)
+                .collect()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+            let _ = std::fs::remove_file(format!("{}.tmp", self.0));
+        }
+    }
+
+    fn message(payload: &str) -> SpooledMessage {
+        message_at(payload, chrono::Utc::now().timestamp())
+    }
+
+    fn message_at(payload: &str, ts: i64) -> SpooledMessage {
+        SpooledMessage {
+            topic: "t".to_string(),
+            payload: payload.to_string(),
+            qos: 1,
+            retain: false,
+            ts,
+        }
+    }
+
+    fn payloads(spool: &mut Spool) -> Vec<String> {
+        let mut payloads = Vec::new();
+        while let Some(msg) = spool.pop_front() {
+            payloads.push(msg.payload);
+        }
+        payloads
+    }
+
+    #[test]
+    fn front_and_remove() {
+        let mut spool = Spool::open(None, 10, None).unwrap();
+        for payload in ["a", "b"] {
+            spool.push(message(payload)).unwrap();
+        }
+        let (id, msg) = spool.front().unwrap();
+        assert_eq!(msg.payload, "a");
+        // Sending does not remove it, only the confirmation does
+        assert_eq!(spool.front().unwrap().0, id);
+        spool.remove(id + 1).unwrap();
+        assert_eq!(spool.len(), 2);
+        spool.remove(id).unwrap();
+        let (next, msg) = spool.front().unwrap();
+        assert_eq!((next, msg.payload.as_str()), (id + 1, "b"));
+        // Removed already
+        spool.remove(id).unwrap();
+        assert_eq!(spool.len(), 1);
+    }
+
+    #[test]
+    fn full_spool_drops_the_front() {
+        let mut spool = Spool::open(None, 2, None).unwrap();
+        spool.push(message("a")).unwrap();
+        let (sending, _) = spool.front().unwrap();
+        spool.push(message("b")).unwrap();
+        spool.push(message("c")).unwrap();
+        assert_eq!(spool.dropped(), 1);
+        // The message being sent was dropped meanwhile, "b" must stay
+        spool.remove(sending).unwrap();
+        assert_eq!(payloads(&mut spool), ["b", "c"]);
+    }
+
+    #[test]
+    fn push_front_restores_the_id() {
+        let mut spool = Spool::open(None, 10, None).unwrap();
+        spool.push(message("a")).unwrap();
+        spool.push(message("b")).unwrap();
+        let (id, _) = spool.front().unwrap();
+        let msg = spool.pop_front().unwrap();
+        spool.push_front(msg);
+        assert_eq!(spool.front().unwrap().0, id);
+        spool.remove(id).unwrap();
+        assert_eq!(payloads(&mut spool), ["b"]);
+    }
+
+    #[test]
+    fn expired_messages_are_dropped() {
+        let now = chrono::Utc::now().timestamp();
+        let mut spool = Spool::open(None, 10, Some(60)).unwrap();
+        spool.push(message_at("old", now - 120)).unwrap();
+        spool.push(message_at("older", now - 61)).unwrap();
+        spool.push(message_at("new", now)).unwrap();
+        assert_eq!(spool.front().unwrap().1.payload, "new");
+        assert_eq!(spool.dropped(), 2);
+        assert_eq!(spool.len(), 1);
+    }
+
+    #[test]
+    fn restore_after_restart() {
+        let file = TempFile::new("restore");
+        {
+            let mut spool = Spool::open(Some(&file.0), 10, None).unwrap();
+            for payload in ["a", "b", "c"] {
+                spool.push(message(payload)).unwrap();
+            }
+            let (id, _) = spool.front().unwrap();
+            spool.remove(id).unwrap();
+            spool.compact().unwrap();
+        }
+        assert_eq!(file.lines().len(), 2);
+
+        let mut spool = Spool::open(Some(&file.0), 10, None).unwrap();
+        assert_eq!(spool.len(), 2);
+        let (id, _) = spool.front().unwrap();
+        spool.remove(id).unwrap();
+        spool.push(message("d")).unwrap();
+        drop(spool);
+
+        // Without compaction the removed line is still in the file: it is
+        // sent again after a crash, never lost
+        let mut spool = Spool::open(Some(&file.0), 10, None).unwrap();
+        assert_eq!(payloads(&mut spool), ["b", "c", "d"]);
+    }
+
+    #[test]
+    fn file_is_emptied_when_all_are_sent() {
+        let file = TempFile::new("empty");
+        let mut spool = Spool::open(Some(&file.0), 10, None).unwrap();
+        spool.push(message("a")).unwrap();
+        let (id, _) = spool.front().unwrap();
+        spool.remove(id).unwrap();
+        assert!(file.lines().is_empty());
+        spool.push(message("b")).unwrap();
+        drop(spool);
+
+        let mut spool = Spool::open(Some(&file.0), 10, None).unwrap();
+        assert_eq!(payloads(&mut spool), ["b"]);
+    }
+
+    #[test]
+    fn corrupted_lines_are_skipped() {
+        let file = TempFile::new("corrupted");
+        let line = |payload| serde_json::to_string(&message(payload)).unwrap();
+        std::fs::write(&file.0, format!("{}\n{{\"topic\":\"t\",\"pay\n{}\n", line("a"), line("b"))).unwrap();
+
+        let mut spool = Spool::open(Some(&file.0), 10, None).unwrap();
+        assert_eq!(spool.len(), 2);
+        // Rewritten without the bad line
+        assert_eq!(file.lines(), [line("a"), line("b")]);
+        assert_eq!(payloads(&mut spool), ["a", "b"]);
+    }
+
+    #[test]
+    fn restore_keeps_the_newest() {
+        let file = TempFile::new("newest");
+        {
+            let mut spool = Spool::open(Some(&file.0), 10, None).unwrap();
+            for payload in ["a", "b", "c", "d"] {
+                spool.push(message(payload)).unwrap();
+            }
+        }
+        let mut spool = Spool::open(Some(&file.0), 2, None).unwrap();
+        assert_eq!(spool.dropped(), 2);
+        assert_eq!(file.lines().len(), 2);
+        assert_eq!(payloads(&mut spool), ["c", "d"]);
+    }
+}
@@ -0,0 +1,287 @@
+// ============================================================================
+// src/store.rs
+// ============================================================================
+use crate::config::PersistConfig;
+use crate::database::{DatabaseClient, DbError, TeleData};
+use crate::influx::InfluxStore;
+use async_trait::async_trait;
+use futures_util::SinkExt;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+const COLUMNS: &str = "td_account, td_object, td_device, td_channel, td_dev_ts, td_store_ts, td_dev_value, td_action";
+const DEFAULT_SQLITE_FILE: &str = "ssn_teledata.db";
+
+/// Columns of the unique index of ssn_teledata from `persist.unique_key`,
+/// none (every row is stored) when not set or empty
+pub fn unique_key(config: Option<&PersistConfig>) -> Option<String> {
+    config
+        .and_then(|c| c.unique_key.as_deref())
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(str::to_string)
+}
+
+/// Storage of ssn_teledata rows used by the database writer
+#[async_trait]
+pub trait TeleStore: Send + Sync {
+    /// Backend name (for logs)
+    fn name(&self) -> &'static str;
+
+    /// Store a batch of rows, all or none. With `unique_key` (columns of a
+    /// unique index) rows already stored are skipped.
+    async fn insert_teledata(&self, rows: &[TeleData], unique_key: Option<&str>) -> Result<(), DbError>;
+}
+
+#[async_trait]
+impl TeleStore for DatabaseClient {
+    fn name(&self) -> &'static str {
+        "postgrest"
+    }
+
+    async fn insert_teledata(&self, rows: &[TeleData], unique_key: Option<&str>) -> Result<(), DbError> {
+        DatabaseClient::insert_teledata(self, rows, unique_key).await
+    }
+}
+
+/// Stores selected by `persist.backend`: `postgrest` (default, needs
+/// POSTGRESTURL), `postgres`, `sqlite` or `influx`. A `persist.influx`
+/// section next to another backend adds InfluxDB as a second store which
+/// gets the same rows. Empty when nothing is configured.
+pub async fn open(
+    config: Option<&PersistConfig>,
+    postgrest: Option<Arc<DatabaseClient>>,
+) -> anyhow::Result<Vec<Arc<dyn TeleStore>>> {
+    let backend = config.and_then(|c| c.backend.as_deref()).unwrap_or("postgrest");
+    let influx = config
+        .and_then(|c| c.influx.as_ref())
+        .map(|influx| InfluxStore::new(influx, postgrest.clone()))
+        .transpose()?;
+
+    let primary: Option<Arc<dyn TeleStore>> = match backend {
+        "postgrest" => postgrest.map(|db| db as Arc<dyn TeleStore>),
+        "postgres" => {
+            let url = config
+                .and_then(|c| c.postgres_url.clone())
+                .ok_or_else(|| anyhow::anyhow!("persist.postgres_url is not set"))?;
+            Some(Arc::new(PostgresStore::new(url)))
+        }
+        "sqlite" => {
+            let path = config
+                .and_then(|c| c.sqlite_file.as_deref())
+                .unwrap_or(DEFAULT_SQLITE_FILE);
+            Some(Arc::new(SqliteStore::open(path, unique_key(config).as_deref())?))
+        }
+        "influx" if influx.is_some() => None,
+        "influx" => anyhow::bail!("persist.backend influx needs a persist.influx section"),
+        other => anyhow::bail!("unknown persist.backend '{}'", other),
+    };
+
+    let stores: Vec<Arc<dyn TeleStore>> = primary
+        .into_iter()
+        .chain(influx.map(|influx| Arc::new(influx) as Arc<dyn TeleStore>))
+        .collect();
+    if !stores.is_empty() {
+        let names: Vec<&str> = stores.iter().map(|s| s.name()).collect();
+        log::info!("Storage backend: {}", names.join(" + "));
+    }
+    Ok(stores)
+}
+
+/// Direct PostgreSQL connection, batches are sent with COPY. The connection
+/// is opened on first use and again after it was lost.
+pub struct PostgresStore {
+    url: String,
+    client: tokio::sync::Mutex<Option<tokio_postgres::Client>>,
+}
+
+impl PostgresStore {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            client: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    async fn connect(&self, unique_key: Option<&str>) -> Result<tokio_postgres::Client, DbError> {
+        let (client, connection) = tokio_postgres::connect(&self.url, tokio_postgres::NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                log::warn!("PostgreSQL connection closed: {}", e);
+            }
+        });
+        // COPY has no ON CONFLICT: with a unique key the rows go through a
+        // temporary table of the session and are inserted from there
+        if unique_key.is_some() {
+            client
+                .batch_execute(
+                    "CREATE TEMP TABLE ssn_teledata_load \
+                     (LIKE ssn_teledata INCLUDING DEFAULTS) ON COMMIT DELETE ROWS",
+                )
+                .await?;
+        }
+        log::info!("PostgreSQL connected");
+        Ok(client)
+    }
+
+    /// Rows in COPY text format
+    fn copy_data(rows: &[TeleData]) -> bytes::Bytes {
+        let mut data = String::new();
+        for r in rows {
+            let _ = writeln!(
+                data,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                r.td_account,
+                r.td_object,
+                copy_escape(&r.td_device),
+                r.td_channel,
+                r.td_dev_ts,
+                r.td_store_ts,
+                r.td_dev_value,
+                r.td_action
+            );
+        }
+        bytes::Bytes::from(data)
+    }
+
+    async fn copy(client: &mut tokio_postgres::Client, rows: &[TeleData], unique_key: Option<&str>) -> Result<(), DbError> {
+        let tx = client.transaction().await?;
+        let table = match unique_key {
+            Some(_) => "ssn_teledata_load",
+            None => "ssn_teledata",
+        };
+
+        let sink = tx
+            .copy_in::<_, bytes::Bytes>(&format!("COPY {} ({}) FROM STDIN", table, COLUMNS))
+            .await?;
+        futures_util::pin_mut!(sink);
+        sink.send(Self::copy_data(rows)).await?;
+        sink.finish().await?;
+
+        if let Some(key) = unique_key {
+            tx.batch_execute(&format!(
+                "INSERT INTO ssn_teledata ({cols}) SELECT {cols} FROM ssn_teledata_load ON CONFLICT ({key}) DO NOTHING",
+                cols = COLUMNS,
+                key = key
+            ))
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// Escape a text value for the COPY text format
+fn copy_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[async_trait]
+impl TeleStore for PostgresStore {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn insert_teledata(&self, rows: &[TeleData], unique_key: Option<&str>) -> Result<(), DbError> {
+        let mut guard = self.client.lock().await;
+        let client = match guard.take() {
+            Some(client) if !client.is_closed() => guard.insert(client),
+            _ => guard.insert(self.connect(unique_key).await?),
+        };
+
+        let result = Self::copy(client, rows, unique_key).await;
+        if let Err(DbError::Postgres(ref e)) = result {
+            if e.is_closed() {
+                *guard = None;
+            }
+        }
+        result
+    }
+}
+
+/// Embedded SQLite database for controllers without a server, the table is
+/// created on open
+pub struct SqliteStore {
+    connection: Arc<Mutex<rusqlite::Connection>>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str, unique_key: Option<&str>) -> anyhow::Result<Self> {
+        let connection = rusqlite::Connection::open(path)?;
+        connection.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS ssn_teledata (
+                 td_account INTEGER NOT NULL,
+                 td_object INTEGER NOT NULL,
+                 td_device TEXT NOT NULL,
+                 td_channel INTEGER NOT NULL,
+                 td_dev_ts INTEGER NOT NULL,
+                 td_store_ts INTEGER NOT NULL,
+                 td_dev_value REAL NOT NULL,
+                 td_action INTEGER NOT NULL DEFAULT 0
+             );
+             CREATE INDEX IF NOT EXISTS ssn_teledata_device
+                 ON ssn_teledata (td_account, td_object, td_device, td_channel, td_dev_ts);",
+        )?;
+        if let Some(key) = unique_key {
+            connection.execute_batch(&format!(
+                "CREATE UNIQUE INDEX IF NOT EXISTS ssn_teledata_unique ON ssn_teledata ({})",
+                key
+            ))?;
+        }
+        log::info!("SQLite database {}", path);
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+}
+
+#[async_trait]
+impl TeleStore for SqliteStore {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    async fn insert_teledata(&self, rows: &[TeleData], unique_key: Option<&str>) -> Result<(), DbError> {
+        let sql = format!(
+            "INSERT {}INTO ssn_teledata ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            if unique_key.is_some() { "OR IGNORE " } else { "" },
+            COLUMNS
+        );
+        let rows: Vec<_> = rows
+            .iter()
+            .map(|r| {
+                (
+                    r.td_account,
+                    r.td_object,
+                    r.td_device.clone(),
+                    r.td_channel,
+                    r.td_dev_ts,
+                    r.td_store_ts,
+                    r.td_dev_value,
+                    r.td_action,
+                )
+            })
+            .collect();
+
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection.lock().unwrap();
+            let tx = connection.transaction()?;
+            {
+                let mut statement = tx.prepare_cached(&sql)?;
+                for (account, object, device, channel, dev_ts, store_ts, value, action) in &rows {
+                    statement.execute(rusqlite::params![account, object, device, channel, dev_ts, store_ts, value, action])?;
+                }
+            }
+            tx.commit()
+        })
+        .await
+        .map_err(|e| DbError::Decode(format!("SQLite task failed: {}", e)))??;
+        Ok(())
+    }
+}
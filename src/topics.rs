@@ -0,0 +1,404 @@
+// ============================================================================
+// src/topics.rs
+// ============================================================================
+use crate::config::TopicTemplates;
+
+pub const DEFAULT_DEVICE: &str = "/ssn/acc/{account}/obj/{object}/device/{device}/{channel}/{suffix}";
+pub const DEFAULT_OBJECT: &str = "/ssn/acc/{account}/obj/{object}/{suffix}";
+pub const DEFAULT_ACCOUNT: &str = "/ssn/acc/{account}/{suffix}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Account,
+    Object,
+    Device,
+    Channel,
+    Suffix,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "account" => Some(Field::Account),
+            "object" => Some(Field::Object),
+            "device" => Some(Field::Device),
+            "channel" => Some(Field::Channel),
+            "suffix" => Some(Field::Suffix),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+/// Values of a topic, the fields absent from the template are left empty
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TopicFields {
+    pub account: u32,
+    pub object: u32,
+    pub device: String,
+    pub channel: u32,
+    pub suffix: String,
+}
+
+/// Channel of a device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceChannel {
+    pub account: u32,
+    pub object: u32,
+    pub device: String,
+    pub channel: u32,
+}
+
+/// Kind of a received topic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Topic {
+    /// Device value (`out`)
+    DeviceOut(DeviceChannel),
+    /// Device value with its metadata (`out_json`)
+    DeviceOutJson(DeviceChannel),
+    /// Value to write to a device (`in`)
+    DeviceIn(DeviceChannel),
+    /// Home Assistant command (`set`)
+    DeviceSet(DeviceChannel),
+    /// Object commands (`commands`)
+    Commands { account: u32, object: u32 },
+    /// Action events (`event`)
+    Event { account: u32, object: u32 },
+    /// Instance status (`status`, `status/info`, `status/heartbeat`)
+    Status { account: u32, object: u32, detail: Option<String> },
+    /// Log records (`log/<source>`)
+    Log { account: u32, source: String },
+}
+
+/// Topic layout given as a template like `/ssn/acc/{account}/obj/{object}/{suffix}`.
+/// Every placeholder takes a whole level, `{suffix}` may only be the last one
+/// and then matches all the remaining levels (e.g. `status/heartbeat`).
+#[derive(Debug, Clone)]
+pub struct TopicTemplate {
+    segments: Vec<Segment>,
+}
+
+impl TopicTemplate {
+    pub fn new(template: &str, required: &[&str]) -> anyhow::Result<Self> {
+        let mut segments = Vec::new();
+        for part in template.split('/') {
+            let segment = match part.strip_prefix('{').and_then(|p| p.strip_suffix('}')) {
+                Some(name) => Segment::Field(
+                    Field::parse(name)
+                        .ok_or_else(|| anyhow::anyhow!("topic template '{}': unknown placeholder {{{}}}", template, name))?,
+                ),
+                None if part.contains(['{', '}', '+', '#']) => {
+                    anyhow::bail!("topic template '{}': invalid level '{}'", template, part)
+                }
+                None => Segment::Literal(part.to_string()),
+            };
+            segments.push(segment);
+        }
+
+        for name in required {
+            let field = Field::parse(name).expect("known placeholder");
+            if !segments.contains(&Segment::Field(field)) {
+                anyhow::bail!("topic template '{}': {{{}}} is missing", template, name);
+            }
+        }
+        if segments[..segments.len() - 1].contains(&Segment::Field(Field::Suffix)) {
+            anyhow::bail!("topic template '{}': {{suffix}} must be the last level", template);
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// Topic for the given values
+    pub fn format(&self, fields: &TopicFields) -> String {
+        self.render(|field| match field {
+            Field::Account => fields.account.to_string(),
+            Field::Object => fields.object.to_string(),
+            Field::Device => fields.device.clone(),
+            Field::Channel => fields.channel.to_string(),
+            Field::Suffix => fields.suffix.clone(),
+        })
+    }
+
+    /// Subscription filter for one account and suffix, other fields are wildcards
+    pub fn filter(&self, account: u32, suffix: &str) -> String {
+        self.render(|field| match field {
+            Field::Account => account.to_string(),
+            Field::Suffix => suffix.to_string(),
+            _ => "+".to_string(),
+        })
+    }
+
+    /// Extract the values of a topic matching this template
+    pub fn parse(&self, topic: &str) -> Option<TopicFields> {
+        let mut fields = TopicFields::default();
+        let mut levels = topic.split('/');
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Field(Field::Suffix) => {
+                    let rest: Vec<&str> = levels.by_ref().collect();
+                    if rest.is_empty() || rest.iter().any(|l| l.is_empty()) {
+                        return None;
+                    }
+                    fields.suffix = rest.join("/");
+                }
+                Segment::Literal(literal) => {
+                    if levels.next()? != literal {
+                        return None;
+                    }
+                }
+                Segment::Field(field) => {
+                    let level = levels.next()?;
+                    match field {
+                        Field::Account => fields.account = level.parse().ok()?,
+                        Field::Object => fields.object = level.parse().ok()?,
+                        Field::Channel => fields.channel = level.parse().ok()?,
+                        Field::Device if !level.is_empty() => fields.device = level.to_string(),
+                        _ => return None,
+                    }
+                }
+            }
+        }
+
+        if levels.next().is_some() {
+            return None;
+        }
+        Some(fields)
+    }
+
+    fn render(&self, value: impl Fn(Field) -> String) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(literal) => literal.clone(),
+                Segment::Field(field) => value(*field),
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// Topic layout of an installation: device channel topics (`out`, `out_json`,
+/// `in`, `set`), object topics (`commands`, `event`, `status`) and account
+/// topics (`log/ssnmqtt`)
+#[derive(Debug, Clone)]
+pub struct TopicScheme {
+    pub device: TopicTemplate,
+    pub object: TopicTemplate,
+    pub account: TopicTemplate,
+}
+
+impl TopicScheme {
+    pub fn from_config(templates: Option<&TopicTemplates>) -> anyhow::Result<Self> {
+        let templates = templates.cloned().unwrap_or_default();
+        Ok(Self {
+            device: TopicTemplate::new(
+                templates.device.as_deref().unwrap_or(DEFAULT_DEVICE),
+                &["account", "object", "device", "channel", "suffix"],
+            )?,
+            object: TopicTemplate::new(
+                templates.object.as_deref().unwrap_or(DEFAULT_OBJECT),
+                &["account", "object", "suffix"],
+            )?,
+            account: TopicTemplate::new(
+                templates.account.as_deref().unwrap_or(DEFAULT_ACCOUNT),
+                &["account", "suffix"],
+            )?,
+        })
+    }
+
+    /// Classify a topic, None if it does not belong to this scheme
+    pub fn parse(&self, topic: &str) -> Option<Topic> {
+        // Device topics first: the object template also matches them with a longer suffix
+        if let Some(fields) = self.device.parse(topic) {
+            let channel = DeviceChannel {
+                account: fields.account,
+                object: fields.object,
+                device: fields.device,
+                channel: fields.channel,
+            };
+            let topic = match fields.suffix.as_str() {
+                "out" => Topic::DeviceOut(channel),
+                "out_json" => Topic::DeviceOutJson(channel),
+                "in" => Topic::DeviceIn(channel),
+                "set" => Topic::DeviceSet(channel),
+                _ => return None,
+            };
+            return Some(topic);
+        }
+
+        if let Some(fields) = self.object.parse(topic) {
+            let (account, object) = (fields.account, fields.object);
+            let topic = match fields.suffix.split_once('/') {
+                None if fields.suffix == "commands" => Topic::Commands { account, object },
+                None if fields.suffix == "event" => Topic::Event { account, object },
+                None if fields.suffix == "status" => Topic::Status { account, object, detail: None },
+                Some(("status", detail)) => Topic::Status {
+                    account,
+                    object,
+                    detail: Some(detail.to_string()),
+                },
+                _ => return None,
+            };
+            return Some(topic);
+        }
+
+        let fields = self.account.parse(topic)?;
+        match fields.suffix.split_once('/') {
+            Some(("log", source)) => Some(Topic::Log {
+                account: fields.account,
+                source: source.to_string(),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn device_topic(&self, account: u32, object: u32, device: &str, channel: u32, suffix: &str) -> String {
+        self.device.format(&TopicFields {
+            account,
+            object,
+            device: device.to_string(),
+            channel,
+            suffix: suffix.to_string(),
+        })
+    }
+
+    pub fn object_topic(&self, account: u32, object: u32, suffix: &str) -> String {
+        self.object.format(&TopicFields {
+            account,
+            object,
+            suffix: suffix.to_string(),
+            ..Default::default()
+        })
+    }
+
+    pub fn account_topic(&self, account: u32, suffix: &str) -> String {
+        self.account.format(&TopicFields {
+            account,
+            suffix: suffix.to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheme() -> TopicScheme {
+        TopicScheme::from_config(None).unwrap()
+    }
+
+    fn channel(device: &str, channel: u32) -> DeviceChannel {
+        DeviceChannel {
+            account: 2,
+            object: 64,
+            device: device.to_string(),
+            channel,
+        }
+    }
+
+    #[test]
+    fn short_device_topic_is_rejected() {
+        // 8 levels, used to panic on a missing channel level
+        assert_eq!(scheme().parse("/ssn/acc/2/obj/64/device/t1"), None);
+        assert_eq!(scheme().parse("/ssn/acc/2/obj/64/device/t1/0"), None);
+    }
+
+    #[test]
+    fn device_suffixes() {
+        let scheme = scheme();
+        let base = "/ssn/acc/2/obj/64/device/t1/3";
+        assert_eq!(scheme.parse(&format!("{}/out", base)), Some(Topic::DeviceOut(channel("t1", 3))));
+        assert_eq!(scheme.parse(&format!("{}/out_json", base)), Some(Topic::DeviceOutJson(channel("t1", 3))));
+        assert_eq!(scheme.parse(&format!("{}/in", base)), Some(Topic::DeviceIn(channel("t1", 3))));
+        assert_eq!(scheme.parse(&format!("{}/set", base)), Some(Topic::DeviceSet(channel("t1", 3))));
+        assert_eq!(scheme.parse(&format!("{}/scaled", base)), None);
+    }
+
+    #[test]
+    fn object_and_account_topics() {
+        let scheme = scheme();
+        assert_eq!(
+            scheme.parse("/ssn/acc/2/obj/64/commands"),
+            Some(Topic::Commands { account: 2, object: 64 })
+        );
+        assert_eq!(scheme.parse("/ssn/acc/2/obj/64/event"), Some(Topic::Event { account: 2, object: 64 }));
+        assert_eq!(
+            scheme.parse("/ssn/acc/2/obj/64/status"),
+            Some(Topic::Status {
+                account: 2,
+                object: 64,
+                detail: None
+            })
+        );
+        assert_eq!(
+            scheme.parse("/ssn/acc/2/obj/64/status/heartbeat"),
+            Some(Topic::Status {
+                account: 2,
+                object: 64,
+                detail: Some("heartbeat".to_string())
+            })
+        );
+        assert_eq!(
+            scheme.parse("/ssn/acc/2/log/ssnmqtt"),
+            Some(Topic::Log {
+                account: 2,
+                source: "ssnmqtt".to_string()
+            })
+        );
+        assert_eq!(scheme.parse("/ssn/acc/2/log"), None);
+    }
+
+    #[test]
+    fn extra_and_trailing_levels_are_rejected() {
+        let scheme = scheme();
+        assert_eq!(scheme.parse("/ssn/acc/2/obj/64/device/t1/0/out/extra"), None);
+        assert_eq!(scheme.parse("/ssn/acc/2/obj/64/device/t1/0/out/"), None);
+        assert_eq!(scheme.parse("/ssn/acc/2/obj/64/commands/"), None);
+        assert_eq!(scheme.parse("/ssn/acc/2/obj/64/commands/extra"), None);
+        assert_eq!(scheme.parse("/ssn/acc/2/obj/64/status/"), None);
+        assert_eq!(scheme.parse("/ssn/acc/2/obj/64/device//0/out"), None);
+    }
+
+    #[test]
+    fn non_numeric_ids_are_rejected() {
+        let scheme = scheme();
+        assert_eq!(scheme.parse("/ssn/acc/x/obj/64/device/t1/0/out"), None);
+        assert_eq!(scheme.parse("/ssn/acc/2/obj/x/device/t1/0/out"), None);
+        assert_eq!(scheme.parse("/ssn/acc/2/obj/64/device/t1/x/out"), None);
+        assert_eq!(scheme.parse("/ssn/acc/-1/obj/64/commands"), None);
+    }
+
+    #[test]
+    fn custom_templates() {
+        let templates = TopicTemplates {
+            device: Some("site/{account}/{object}/{device}/{channel}/{suffix}".to_string()),
+            object: Some("site/{account}/{object}/{suffix}".to_string()),
+            account: None,
+        };
+        let scheme = TopicScheme::from_config(Some(&templates)).unwrap();
+
+        assert_eq!(scheme.device_topic(2, 64, "t1", 0, "out"), "site/2/64/t1/0/out");
+        assert_eq!(scheme.device.filter(2, "in"), "site/2/+/+/+/in");
+        assert_eq!(scheme.object_topic(2, 64, "status"), "site/2/64/status");
+        assert_eq!(scheme.account_topic(2, "log/ssnmqtt"), "/ssn/acc/2/log/ssnmqtt");
+
+        assert_eq!(scheme.parse("site/2/64/t1/0/out"), Some(Topic::DeviceOut(channel("t1", 0))));
+        assert_eq!(scheme.parse("site/2/64/commands"), Some(Topic::Commands { account: 2, object: 64 }));
+        assert_eq!(scheme.parse("/ssn/acc/2/obj/64/device/t1/0/out"), None);
+    }
+
+    #[test]
+    fn invalid_templates() {
+        assert!(TopicTemplate::new("/ssn/{account}/{suffix}/x", &["account", "suffix"]).is_err());
+        assert!(TopicTemplate::new("/ssn/{account}/{bogus}/{suffix}", &["account", "suffix"]).is_err());
+        assert!(TopicTemplate::new("/ssn/{object}/{suffix}", &["account", "object", "suffix"]).is_err());
+        assert!(TopicTemplate::new("/ssn/+/{account}/{suffix}", &["account", "suffix"]).is_err());
+    }
+}